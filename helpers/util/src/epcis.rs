@@ -0,0 +1,561 @@
+//! EPCIS 2.0 JSON-LD documents of shipment histories.
+//!
+//! A shipment's events become `ObjectEvent`s, except those recorded on the container it was
+//! loaded in, which become `AggregationEvent`s of the container. Sensor readings are listed in
+//! the standard `sensorElementList`, with the UN/CEFACT code of their unit. Identifiers are written as `urn:bluefn:` URIs, and device
+//! signatures as a `bluefn:signature` extension, so that `parse_document` gives back the events
+//! exactly as they were exported.
+//!
+//! Only available with the `std` feature: documents are built off-chain, from the data
+//! returned by the tracks runtime API.
+
+use serde_json::{json, Map, Value};
+use sp_core::{bytes, sr25519};
+use sp_std::ops::Range;
+
+use crate::shipment::*;
+
+pub const EPCIS_CONTEXT: &str = "https://ref.gs1.org/standards/epcis/2.0.0/epcis-context.jsonld";
+pub const BLUEFN_CONTEXT: &str = "https://github.com/samlet/bluefn-util/epcis#";
+
+const SHIPMENT_URI: &str = "urn:bluefn:shipment:";
+const EVENT_URI: &str = "urn:bluefn:event:";
+const DEVICE_URI: &str = "urn:bluefn:device:";
+const GEO_URI: &str = "geo:";
+
+const EVENT_TYPES: [ShippingEventType; 11] = [
+    ShippingEventType::ShipmentPickup,
+    ShippingEventType::SensorReading,
+    ShippingEventType::ShipmentDelivery,
+    ShippingEventType::ShipmentCancellation,
+    ShippingEventType::ShipmentReturn,
+    ShippingEventType::ShipmentLoss,
+    ShippingEventType::ShipmentDamage,
+    ShippingEventType::ShipmentHold,
+    ShippingEventType::CustodyHandoff,
+    ShippingEventType::ShipmentDisaggregation,
+    ShippingEventType::OwnershipTransfer,
+];
+
+const READING_TYPES: [ReadingType; 6] = [
+    ReadingType::Humidity,
+    ReadingType::Pressure,
+    ReadingType::Shock,
+    ReadingType::Tilt,
+    ReadingType::Temperature,
+    ReadingType::Vibration,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    // The document isn't JSON
+    InvalidJson,
+    MissingField(&'static str),
+    InvalidField(&'static str),
+}
+
+// CBV business step and disposition of a type of shipping event.
+// Business steps are unique, they tell the type back when parsing.
+fn cbv_terms(event_type: &ShippingEventType) -> (&'static str, Option<&'static str>) {
+    match event_type {
+        ShippingEventType::ShipmentPickup => ("departing", Some("in_transit")),
+        ShippingEventType::SensorReading => ("sensor_reporting", None),
+        ShippingEventType::ShipmentDelivery => ("receiving", Some("in_progress")),
+        ShippingEventType::ShipmentCancellation => ("void_shipping", None),
+        ShippingEventType::ShipmentReturn => ("shipping", Some("returned")),
+        ShippingEventType::ShipmentLoss => ("transporting", Some("unknown")),
+        ShippingEventType::ShipmentDamage => ("inspecting", Some("damaged")),
+        ShippingEventType::ShipmentHold => ("holding", Some("unavailable")),
+        ShippingEventType::CustodyHandoff => ("accepting", Some("in_transit")),
+        ShippingEventType::ShipmentDisaggregation => ("unpacking", None),
+        // CBV has no business step for a sale of goods in transit
+        ShippingEventType::OwnershipTransfer => ("other", None),
+    }
+}
+
+// CBV measurement type of a reading. Vibrations are reported by their frequency.
+fn sensor_type(reading_type: &ReadingType) -> &'static str {
+    match reading_type {
+        ReadingType::Humidity => "gs1:RelativeHumidity",
+        ReadingType::Pressure => "gs1:AbsolutePressure",
+        ReadingType::Shock => "gs1:Acceleration",
+        ReadingType::Tilt => "gs1:Angle",
+        ReadingType::Temperature => "gs1:Temperature",
+        ReadingType::Vibration => "gs1:Frequency",
+    }
+}
+
+// UN/CEFACT code of the unit readings of a type are recorded in
+fn sensor_uom(reading_type: &ReadingType) -> &'static str {
+    match reading_type {
+        // Percent
+        ReadingType::Humidity => "P1",
+        ReadingType::Pressure => "KPA",
+        // Standard gravity, g
+        ReadingType::Shock => "K40",
+        // Degree of angle
+        ReadingType::Tilt => "DD",
+        ReadingType::Temperature => "CEL",
+        ReadingType::Vibration => "HTZ",
+    }
+}
+
+/// Builds the EPCIS document of a shipment's history, from its events in the order they
+/// were recorded. Events of the shipment's container are exported as `AggregationEvent`s.
+pub fn export_shipment<AccountId, Moment: Copy + Into<u64>>(
+    shipment: &Shipment<AccountId, Moment>,
+    events: &[ShippingEvent<Moment>],
+) -> Value {
+    let created = events.last().map_or(shipment.registered, |event| event.timestamp);
+    json!({
+        "@context": [EPCIS_CONTEXT, { "bluefn": BLUEFN_CONTEXT }],
+        "type": "EPCISDocument",
+        "schemaVersion": "2.0",
+        "creationDate": format_time(created.into()),
+        "epcisBody": {
+            "eventList": events
+                .iter()
+                .map(|event| export_event(&shipment.id, event))
+                .collect::<Vec<_>>(),
+        },
+    })
+}
+
+fn export_event<Moment: Copy + Into<u64>>(shipment_id: &[u8], event: &ShippingEvent<Moment>) -> Value {
+    let (biz_step, disposition) = cbv_terms(&event.event_type);
+    let own = event.shipment_id.as_slice() == shipment_id;
+    let disaggregation = event.event_type == ShippingEventType::ShipmentDisaggregation;
+
+    let mut object = Map::new();
+    if own && !disaggregation {
+        object.insert("type".into(), json!("ObjectEvent"));
+        object.insert("epcList".into(), json!([uri(SHIPMENT_URI, &event.shipment_id)]));
+        object.insert("action".into(), json!("OBSERVE"));
+    } else {
+        object.insert("type".into(), json!("AggregationEvent"));
+        object.insert("parentID".into(), json!(uri(SHIPMENT_URI, &event.shipment_id)));
+        // A container unloading lists no child, as all of them are unloaded
+        if !own {
+            object.insert("childEPCs".into(), json!([uri(SHIPMENT_URI, shipment_id)]));
+        }
+        object.insert("action".into(), json!(if disaggregation { "DELETE" } else { "OBSERVE" }));
+    }
+    object.insert("eventID".into(), json!(uri(EVENT_URI, &event.id)));
+    object.insert("eventTime".into(), json!(format_time(event.timestamp.into())));
+    object.insert("eventTimeZoneOffset".into(), json!("+00:00"));
+    object.insert("bizStep".into(), json!(biz_step));
+    if let Some(disposition) = disposition {
+        object.insert("disposition".into(), json!(disposition));
+    }
+    if let Some(point) = &event.location {
+        let geo = format!(
+            "{}{},{}",
+            GEO_URI,
+            point.latitude.to_num::<f64>(),
+            point.longitude.to_num::<f64>()
+        );
+        object.insert("readPoint".into(), json!({ "id": geo }));
+    }
+    if !event.readings.is_empty() {
+        let elements = event.readings.iter().map(export_reading).collect::<Vec<_>>();
+        object.insert("sensorElementList".into(), json!(elements));
+    }
+    Value::Object(object)
+}
+
+// Readings may come from different devices at different times,
+// so each one gets its own sensor element
+fn export_reading<Moment: Copy + Into<u64>>(reading: &Reading<Moment>) -> Value {
+    json!({
+        "sensorMetadata": {
+            "time": format_time(reading.timestamp.into()),
+            "deviceID": uri(DEVICE_URI, &reading.device_id),
+            "bluefn:signature": bytes::to_hex(&reading.signature.0, false),
+        },
+        "sensorReport": [{
+            "type": sensor_type(&reading.reading_type),
+            "uom": sensor_uom(&reading.reading_type),
+            // Every `Decimal` is exactly representable as a f64
+            "value": reading.value.to_num::<f64>(),
+        }],
+    })
+}
+
+/// Parses the events of an EPCIS document, as exported by `export_shipment`
+pub fn parse_document<Moment: From<u64>>(document: &str) -> Result<Vec<ShippingEvent<Moment>>, ParseError> {
+    let document: Value = serde_json::from_str(document).map_err(|_| ParseError::InvalidJson)?;
+    document
+        .pointer("/epcisBody/eventList")
+        .and_then(Value::as_array)
+        .ok_or(ParseError::MissingField("eventList"))?
+        .iter()
+        .map(parse_event)
+        .collect()
+}
+
+fn parse_event<Moment: From<u64>>(event: &Value) -> Result<ShippingEvent<Moment>, ParseError> {
+    let biz_step = str_field(event, "bizStep")?;
+    let event_type = EVENT_TYPES
+        .iter()
+        .find(|event_type| cbv_terms(event_type).0 == biz_step)
+        .cloned()
+        .ok_or(ParseError::InvalidField("bizStep"))?;
+
+    // Events of a container are aggregation events, with the container as parent
+    let shipment_id = match str_field(event, "type")? {
+        "ObjectEvent" => {
+            let epc = event
+                .pointer("/epcList/0")
+                .and_then(Value::as_str)
+                .ok_or(ParseError::MissingField("epcList"))?;
+            from_uri(epc, SHIPMENT_URI).ok_or(ParseError::InvalidField("epcList"))?
+        }
+        "AggregationEvent" => {
+            from_uri(str_field(event, "parentID")?, SHIPMENT_URI).ok_or(ParseError::InvalidField("parentID"))?
+        }
+        _ => return Err(ParseError::InvalidField("type")),
+    };
+
+    let location = match event.pointer("/readPoint/id").and_then(Value::as_str) {
+        Some(geo) => Some(parse_geo(geo).ok_or(ParseError::InvalidField("readPoint"))?),
+        None => None,
+    };
+    let readings = match event.get("sensorElementList").and_then(Value::as_array) {
+        Some(elements) => elements.iter().map(parse_reading).collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+
+    Ok(ShippingEvent {
+        id: from_uri(str_field(event, "eventID")?, EVENT_URI).ok_or(ParseError::InvalidField("eventID"))?,
+        event_type,
+        shipment_id,
+        location,
+        readings,
+        timestamp: parse_time(str_field(event, "eventTime")?)
+            .ok_or(ParseError::InvalidField("eventTime"))?
+            .into(),
+    })
+}
+
+fn parse_reading<Moment: From<u64>>(element: &Value) -> Result<Reading<Moment>, ParseError> {
+    let metadata = element
+        .get("sensorMetadata")
+        .ok_or(ParseError::MissingField("sensorMetadata"))?;
+    let report = element
+        .pointer("/sensorReport/0")
+        .ok_or(ParseError::MissingField("sensorReport"))?;
+
+    let sensor = str_field(report, "type")?;
+    let reading_type = READING_TYPES
+        .iter()
+        .find(|reading_type| sensor_type(reading_type) == sensor)
+        .cloned()
+        .ok_or(ParseError::InvalidField("sensorReport"))?;
+    if str_field(report, "uom")? != sensor_uom(&reading_type) {
+        return Err(ParseError::InvalidField("uom"));
+    }
+    // Values out of the `Decimal` range are rejected
+    let value = report
+        .get("value")
+        .and_then(Value::as_f64)
+        .and_then(Decimal::checked_from_num)
+        .ok_or(ParseError::InvalidField("value"))?;
+    let signature = bytes::from_hex(str_field(metadata, "bluefn:signature")?)
+        .ok()
+        .filter(|signature| signature.len() == 64)
+        .map(|signature| sr25519::Signature::from_slice(&signature))
+        .ok_or(ParseError::InvalidField("bluefn:signature"))?;
+
+    Ok(Reading {
+        device_id: from_uri(str_field(metadata, "deviceID")?, DEVICE_URI)
+            .ok_or(ParseError::InvalidField("deviceID"))?,
+        reading_type,
+        timestamp: parse_time(str_field(metadata, "time")?)
+            .ok_or(ParseError::InvalidField("time"))?
+            .into(),
+        value,
+        signature,
+    })
+}
+
+fn str_field<'a>(value: &'a Value, field: &'static str) -> Result<&'a str, ParseError> {
+    value
+        .get(field)
+        .ok_or(ParseError::MissingField(field))?
+        .as_str()
+        .ok_or(ParseError::InvalidField(field))
+}
+
+// Identifiers are expected to be UTF-8, which is the case of GS1 keys
+fn uri(prefix: &str, id: &[u8]) -> String {
+    format!("{}{}", prefix, String::from_utf8_lossy(id))
+}
+
+fn from_uri(uri: &str, prefix: &str) -> Option<Identifier> {
+    if uri.starts_with(prefix) && uri.len() > prefix.len() {
+        Some(uri[prefix.len()..].as_bytes().to_vec())
+    } else {
+        None
+    }
+}
+
+// Coordinates have at most 41 significant bits, f64 represents them exactly
+fn parse_geo(geo: &str) -> Option<ReadPoint> {
+    if !geo.starts_with(GEO_URI) {
+        return None;
+    }
+    let mut coordinates = geo[GEO_URI.len()..].splitn(2, ',');
+    let latitude = coordinates.next()?.parse::<f64>().ok()?;
+    let longitude = coordinates.next()?.parse::<f64>().ok()?;
+    let point = ReadPoint {
+        latitude: Coordinate::checked_from_num(latitude)?,
+        longitude: Coordinate::checked_from_num(longitude)?,
+    };
+    if point.has_valid_latitude() && point.has_valid_longitude() {
+        Some(point)
+    } else {
+        None
+    }
+}
+
+const MILLIS_PER_DAY: u64 = 86_400_000;
+
+/// Formats milliseconds since the Unix epoch as an UTC date and time, e.g. `2020-09-13T12:26:40.123Z`
+pub fn format_time(millis: u64) -> String {
+    let (year, month, day) = civil_from_days(millis / MILLIS_PER_DAY);
+    let millis_of_day = millis % MILLIS_PER_DAY;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        millis_of_day / 3_600_000,
+        millis_of_day / 60_000 % 60,
+        millis_of_day / 1000 % 60,
+        millis_of_day % 1000
+    )
+}
+
+/// Parses an ISO 8601 date and time with a time zone offset, such as EPCIS `eventTime`s,
+/// into milliseconds since the Unix epoch. Fractions of a millisecond are dropped.
+pub fn parse_time(time: &str) -> Option<u64> {
+    if time.len() < 19 || !time.is_char_boundary(19) {
+        return None;
+    }
+    let (date_time, mut rest) = time.split_at(19);
+    let layout = date_time.as_bytes();
+    if layout[4] != b'-' || layout[7] != b'-' || layout[10] != b'T' || layout[13] != b':' || layout[16] != b':' {
+        return None;
+    }
+    let number = |range: Range<usize>| {
+        let digits = &date_time[range];
+        if digits.bytes().all(|c| c.is_ascii_digit()) {
+            digits.parse::<u64>().ok()
+        } else {
+            None
+        }
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if year < 1970 || month < 1 || month > 12 || day < 1 || day > 31 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let mut millis = 0;
+    if rest.starts_with('.') {
+        let digits = rest[1..].bytes().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        let fraction = &rest[1..1 + digits];
+        millis = format!("{:0<3}", &fraction[..digits.min(3)]).parse::<u64>().ok()?;
+        rest = &rest[1 + digits..];
+    }
+
+    let offset_minutes: i64 = match rest.as_bytes() {
+        b"Z" => 0,
+        [sign @ b'+', h1, h2, b':', m1, m2] | [sign @ b'-', h1, h2, b':', m1, m2] => {
+            let digits = [*h1, *h2, *m1, *m2];
+            if !digits.iter().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let value = |high: u8, low: u8| ((high - b'0') * 10 + (low - b'0')) as i64;
+            let minutes = value(digits[0], digits[1]) * 60 + value(digits[2], digits[3]);
+            if *sign == b'-' { -minutes } else { minutes }
+        }
+        _ => return None,
+    };
+
+    let local = days_from_civil(year, month, day) * MILLIS_PER_DAY
+        + ((hour * 60 + minute) * 60 + second) * 1000
+        + millis;
+    let utc = local as i64 - offset_minutes * 60_000;
+    if utc < 0 {
+        None
+    } else {
+        Some(utc as u64)
+    }
+}
+
+// Civil dates from and to days since the Unix epoch,
+// see http://howardhinnant.github.io/date_algorithms.html
+// Only dates from 1970 are handled, hence the unsigned arithmetic.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(reading_type: ReadingType, value: f64, timestamp: u64) -> Reading<u64> {
+        Reading {
+            device_id: b"14d453ea4bdf46bc8042".to_vec(),
+            reading_type,
+            timestamp,
+            value: Decimal::from_num(value),
+            signature: sr25519::Signature::from_raw([7; 64]),
+        }
+    }
+
+    fn event(id: &str, shipment_id: &[u8], event_type: ShippingEventType, timestamp: u64) -> ShippingEvent<u64> {
+        ShippingEvent {
+            id: id.as_bytes().to_vec(),
+            event_type,
+            shipment_id: shipment_id.to_vec(),
+            location: None,
+            readings: vec![],
+            timestamp,
+        }
+    }
+
+    fn history() -> (Shipment<u64, u64>, Vec<ShippingEvent<u64>>) {
+        let shipment = Shipment {
+            id: b"0001".to_vec(),
+            owner: 1,
+            custodian: 1,
+            consignee: None,
+            status: ShipmentStatus::Delivered,
+            products: vec![],
+            registered: 1_600_000_000_000,
+            delivered: Some(1_600_000_300_000),
+            excursions: 0,
+        };
+        let mut pickup = event("0000000001", b"0001", ShippingEventType::ShipmentPickup, 1_600_000_100_000);
+        pickup.location = Some(ReadPoint {
+            latitude: Coordinate::from_num(52.4941126),
+            longitude: Coordinate::from_num(-13.4355606),
+        });
+        // Recorded on the container the shipment was loaded in
+        let mut sensing = event("0000000002", b"C001", ShippingEventType::SensorReading, 1_600_000_200_000);
+        sensing.readings = vec![
+            reading(ReadingType::Temperature, 4.75, 1_600_000_199_500),
+            reading(ReadingType::Humidity, 61.5, 1_600_000_199_750),
+        ];
+        let delivery = event("0000000003", b"C001", ShippingEventType::ShipmentDelivery, 1_600_000_300_000);
+        let unloading = event("0000000004", b"C001", ShippingEventType::ShipmentDisaggregation, 1_600_000_300_001);
+        (shipment, vec![pickup, sensing, delivery, unloading])
+    }
+
+    #[test]
+    fn export_maps_events() {
+        let (shipment, events) = history();
+        let document = export_shipment(&shipment, &events);
+        let event_list = document["epcisBody"]["eventList"].as_array().unwrap();
+
+        assert_eq!(document["creationDate"], json!("2020-09-13T12:31:40.001Z"));
+        assert_eq!(event_list[0]["type"], json!("ObjectEvent"));
+        assert_eq!(event_list[0]["epcList"], json!(["urn:bluefn:shipment:0001"]));
+        assert_eq!(event_list[0]["bizStep"], json!("departing"));
+        assert_eq!(event_list[0]["readPoint"]["id"].as_str().map(|id| id.starts_with("geo:52.49")), Some(true));
+
+        assert_eq!(event_list[1]["type"], json!("AggregationEvent"));
+        assert_eq!(event_list[1]["parentID"], json!("urn:bluefn:shipment:C001"));
+        assert_eq!(event_list[1]["childEPCs"], json!(["urn:bluefn:shipment:0001"]));
+        assert_eq!(
+            event_list[1]["sensorElementList"][0]["sensorReport"][0],
+            json!({ "type": "gs1:Temperature", "uom": "CEL", "value": 4.75 })
+        );
+        assert_eq!(event_list[1]["sensorElementList"][1]["sensorMetadata"]["time"], json!("2020-09-13T12:29:59.750Z"));
+
+        assert_eq!(event_list[3]["action"], json!("DELETE"));
+        assert_eq!(event_list[3]["bizStep"], json!("unpacking"));
+    }
+
+    #[test]
+    fn parse_round_trips() {
+        let (shipment, events) = history();
+        let document = export_shipment(&shipment, &events).to_string();
+
+        assert_eq!(parse_document::<u64>(&document), Ok(events));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_terms() {
+        let (shipment, events) = history();
+        let document = export_shipment(&shipment, &events[..1])
+            .to_string()
+            .replace("departing", "loading");
+
+        assert_eq!(parse_document::<u64>(&document), Err(ParseError::InvalidField("bizStep")));
+        assert_eq!(parse_document::<u64>("{"), Err(ParseError::InvalidJson));
+        assert_eq!(parse_document::<u64>("{}"), Err(ParseError::MissingField("eventList")));
+    }
+
+    #[test]
+    fn parse_accepts_negative_readings() {
+        let (shipment, events) = history();
+        let document = export_shipment(&shipment, &events)
+            .to_string()
+            .replace(r#""value":4.75"#, r#""value":-18.5"#);
+
+        let parsed = parse_document::<u64>(&document).unwrap();
+        assert_eq!(parsed[1].readings[0].value, Decimal::from_num(-18.5));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_readings() {
+        let (shipment, events) = history();
+        let document = export_shipment(&shipment, &events).to_string();
+        assert!(document.contains(r#""value":4.75"#));
+
+        let out_of_range = document.replace(r#""value":4.75"#, r#""value":40000"#);
+        assert_eq!(parse_document::<u64>(&out_of_range), Err(ParseError::InvalidField("value")));
+
+        let fahrenheit = document.replace(r#""uom":"CEL""#, r#""uom":"FAH""#);
+        assert_eq!(parse_document::<u64>(&fahrenheit), Err(ParseError::InvalidField("uom")));
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(format_time(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_time(1_600_000_000_123), "2020-09-13T12:26:40.123Z");
+        assert_eq!(format_time(951_782_400_000), "2000-02-29T00:00:00.000Z");
+
+        assert_eq!(parse_time("2020-09-13T12:26:40.123Z"), Some(1_600_000_000_123));
+        assert_eq!(parse_time("2020-09-13T14:26:40.1234+02:00"), Some(1_600_000_000_123));
+        assert_eq!(parse_time("2020-09-13T07:26:40-05:00"), Some(1_600_000_000_000));
+        assert_eq!(parse_time("2000-02-29T00:00:00.000Z"), Some(951_782_400_000));
+        assert_eq!(parse_time("2020-09-13 12:26:40Z"), None);
+        assert_eq!(parse_time("2020-09-13T12:26:40"), None);
+        assert_eq!(parse_time("1969-12-31T23:59:59Z"), None);
+    }
+}
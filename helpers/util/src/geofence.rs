@@ -0,0 +1,168 @@
+use frame_support::{
+    codec::{Decode, Encode},
+    sp_runtime::RuntimeDebug,
+};
+use fixed::types::I64F64;
+use sp_std::prelude::*;
+use crate::shipment::ReadPoint;
+
+// General constraints to limit data size
+pub const GEOFENCE_MIN_VERTICES: usize = 3;
+pub const GEOFENCE_MAX_VERTICES: usize = 8;
+// Mean Earth radius, in meters
+pub const EARTH_RADIUS: u32 = 6_371_000;
+
+// Geometry is computed with a wider type than `Coordinate`, so that squared
+// distances of a few meters (in radians) don't round down to zero.
+type Wide = I64F64;
+
+// π / 180, as the bits of an I64F64
+const DEGREES_TO_RADIANS_BITS: i128 = 321_956_420_358_983_237;
+
+// An area a shipment is expected to be delivered in.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum Geofence {
+    // A circle around `center`, with a `radius` in meters
+    Circle { center: ReadPoint, radius: u32 },
+    // A small polygon, given by its vertices in order.
+    // Polygons crossing the antimeridian are not supported.
+    Polygon(Vec<ReadPoint>),
+}
+
+impl Geofence {
+    pub fn is_valid(&self) -> bool {
+        let is_valid_point = |point: &ReadPoint| point.has_valid_latitude() && point.has_valid_longitude();
+        match self {
+            Geofence::Circle { center, radius } => *radius > 0 && is_valid_point(center),
+            Geofence::Polygon(vertices) => {
+                vertices.len() >= GEOFENCE_MIN_VERTICES
+                    && vertices.len() <= GEOFENCE_MAX_VERTICES
+                    && vertices.iter().all(is_valid_point)
+            }
+        }
+    }
+
+    pub fn contains(&self, point: &ReadPoint) -> bool {
+        match self {
+            Geofence::Circle { center, radius } => within_radius(center, point, *radius),
+            Geofence::Polygon(vertices) => within_polygon(vertices, point),
+        }
+    }
+}
+
+fn radians(degrees: Wide) -> Wide {
+    degrees * Wide::from_bits(DEGREES_TO_RADIANS_BITS)
+}
+
+// Taylor series of the cosine, accurate to ~1e-5 on [-π/2, π/2] which covers all latitudes
+fn cos(x: Wide) -> Wide {
+    let x2 = x * x;
+    let mut term = Wide::from_num(1);
+    let mut sum = term;
+    for n in 1..5i32 {
+        term = -term * x2 / Wide::from_num((2 * n - 1) * (2 * n));
+        sum += term;
+    }
+    sum
+}
+
+// Equirectangular approximation of the distance, good enough at geofence scale.
+// Squared distances are compared, to avoid a square root.
+fn within_radius(center: &ReadPoint, point: &ReadPoint, radius: u32) -> bool {
+    let (lat1, lon1) = (Wide::from_num(center.latitude), Wide::from_num(center.longitude));
+    let (lat2, lon2) = (Wide::from_num(point.latitude), Wide::from_num(point.longitude));
+
+    // Take the short way around the antimeridian
+    let half_turn = Wide::from_num(180);
+    let mut dlon = lon2 - lon1;
+    if dlon > half_turn {
+        dlon -= half_turn * 2;
+    } else if dlon < -half_turn {
+        dlon += half_turn * 2;
+    }
+
+    let mean_lat = radians((lat1 + lat2) / 2);
+    let x = radians(dlon) * cos(mean_lat);
+    let y = radians(lat2 - lat1);
+    let max = Wide::from_num(radius) / Wide::from_num(EARTH_RADIUS);
+
+    x * x + y * y <= max * max
+}
+
+// Ray casting: a point is inside when a ray from it crosses the polygon's edges an odd number of times
+fn within_polygon(vertices: &[ReadPoint], point: &ReadPoint) -> bool {
+    let (px, py) = (Wide::from_num(point.longitude), Wide::from_num(point.latitude));
+    let mut inside = false;
+    let mut j = match vertices.len() {
+        0 => return false,
+        len => len - 1,
+    };
+    for i in 0..vertices.len() {
+        let (xi, yi) = (Wide::from_num(vertices[i].longitude), Wide::from_num(vertices[i].latitude));
+        let (xj, yj) = (Wide::from_num(vertices[j].longitude), Wide::from_num(vertices[j].latitude));
+        if (yi > py) != (yj > py) {
+            let x_cross = (xj - xi) * (py - yi) / (yj - yi) + xi;
+            if px < x_cross {
+                inside = !inside;
+            }
+        }
+        j = i;
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shipment::Coordinate;
+
+    fn point(latitude: f64, longitude: f64) -> ReadPoint {
+        ReadPoint {
+            latitude: Coordinate::from_num(latitude),
+            longitude: Coordinate::from_num(longitude),
+        }
+    }
+
+    #[test]
+    fn circle_contains() {
+        let fence = Geofence::Circle { center: point(52.52, 13.405), radius: 500 };
+
+        // ~100m north
+        assert!(fence.contains(&point(52.5209, 13.405)));
+        // ~440m east, longitudes shrink away from the equator
+        assert!(fence.contains(&point(52.52, 13.4115)));
+        // ~1km north
+        assert!(!fence.contains(&point(52.529, 13.405)));
+        // Southern hemisphere
+        assert!(!fence.contains(&point(-52.52, 13.405)));
+    }
+
+    #[test]
+    fn circle_across_antimeridian() {
+        let fence = Geofence::Circle { center: point(-16.5, 179.999), radius: 1_000 };
+
+        assert!(fence.contains(&point(-16.5, -179.999)));
+    }
+
+    #[test]
+    fn polygon_contains() {
+        let fence = Geofence::Polygon(vec![
+            point(-34.60, -58.39),
+            point(-34.60, -58.37),
+            point(-34.62, -58.37),
+            point(-34.62, -58.39),
+        ]);
+
+        assert!(fence.contains(&point(-34.61, -58.38)));
+        assert!(!fence.contains(&point(-34.63, -58.38)));
+        assert!(!fence.contains(&point(34.61, 58.38)));
+    }
+
+    #[test]
+    fn validity() {
+        assert!(!Geofence::Circle { center: point(52.52, 13.405), radius: 0 }.is_valid());
+        assert!(!Geofence::Circle { center: point(91.0, 13.405), radius: 10 }.is_valid());
+        assert!(!Geofence::Polygon(vec![point(0.0, 0.0), point(1.0, 1.0)]).is_valid());
+        assert!(Geofence::Polygon(vec![point(0.0, 0.0), point(1.0, 1.0), point(0.0, 1.0)]).is_valid());
+    }
+}
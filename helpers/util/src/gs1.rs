@@ -0,0 +1,107 @@
+// GS1 identification keys, see the GS1 General Specifications.
+// Keys are numeric strings whose last digit is a mod-10 check digit, which catches
+// every single digit typo and most transpositions.
+
+pub const GTIN_LENGTHS: [usize; 4] = [8, 12, 13, 14];
+pub const SSCC_LENGTH: usize = 18;
+pub const GLN_LENGTH: usize = 13;
+
+// Types that implement the IdentifierValidator trait tell whether an identifier is well-formed.
+// Pallets choose one through their `Trait`, so that malformed identifiers are refused on-chain.
+pub trait IdentifierValidator {
+    fn is_valid(id: &[u8]) -> bool;
+}
+
+// Accepts any identifier, for chains that don't use GS1 keys
+pub struct AnyIdentifier;
+
+impl IdentifierValidator for AnyIdentifier {
+    fn is_valid(_id: &[u8]) -> bool {
+        true
+    }
+}
+
+// Global Trade Item Number, identifying products: GTIN-8, GTIN-12 (UPC), GTIN-13 (EAN) or GTIN-14
+pub struct Gtin;
+
+impl IdentifierValidator for Gtin {
+    fn is_valid(id: &[u8]) -> bool {
+        GTIN_LENGTHS.contains(&id.len()) && has_valid_check_digit(id)
+    }
+}
+
+// Serial Shipping Container Code, identifying logistic units
+pub struct Sscc;
+
+impl IdentifierValidator for Sscc {
+    fn is_valid(id: &[u8]) -> bool {
+        id.len() == SSCC_LENGTH && has_valid_check_digit(id)
+    }
+}
+
+// Global Location Number, identifying parties and locations
+pub struct Gln;
+
+impl IdentifierValidator for Gln {
+    fn is_valid(id: &[u8]) -> bool {
+        id.len() == GLN_LENGTH && has_valid_check_digit(id)
+    }
+}
+
+// Digits are weighted 3 and 1 alternately, from the rightmost one before the check digit.
+// The check digit brings the weighted sum to a multiple of 10.
+pub fn has_valid_check_digit(id: &[u8]) -> bool {
+    let (check, digits) = match id.split_last() {
+        Some((check, digits)) if !digits.is_empty() => (check, digits),
+        _ => return false,
+    };
+    if !id.iter().all(u8::is_ascii_digit) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| (digit - b'0') as u32 * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+    (10 - sum % 10) % 10 == (check - b'0') as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gtin() {
+        assert!(Gtin::is_valid(b"96385074"));
+        assert!(Gtin::is_valid(b"012345678905"));
+        assert!(Gtin::is_valid(b"4006381333931"));
+        assert!(Gtin::is_valid(b"00012345678905"));
+
+        // Typo, transposition, wrong length and non-digits
+        assert!(!Gtin::is_valid(b"4006381333932"));
+        assert!(!Gtin::is_valid(b"4006381339331"));
+        assert!(!Gtin::is_valid(b"400638133393"));
+        assert!(!Gtin::is_valid(b"40063813339a1"));
+        assert!(!Gtin::is_valid(b""));
+    }
+
+    #[test]
+    fn sscc() {
+        assert!(Sscc::is_valid(b"106141412345678908"));
+        assert!(!Sscc::is_valid(b"106141412345678907"));
+        assert!(!Sscc::is_valid(b"4006381333931"));
+    }
+
+    #[test]
+    fn gln() {
+        assert!(Gln::is_valid(b"0614141000418"));
+        assert!(!Gln::is_valid(b"0614141000415"));
+        assert!(!Gln::is_valid(b"106141412345678908"));
+    }
+
+    #[test]
+    fn any_identifier() {
+        assert!(AnyIdentifier::is_valid(b"0001"));
+    }
+}
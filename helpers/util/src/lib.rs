@@ -1,6 +1,11 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 pub mod catalog;
+#[cfg(feature = "std")]
+pub mod epcis;
+pub mod geofence;
+pub mod gs1;
+pub mod merkle;
 pub mod shipment;
 
 use sp_core::{sr25519, Pair, H256};
@@ -0,0 +1,103 @@
+use sp_runtime::traits::Hash;
+use sp_std::prelude::*;
+
+// Binary Merkle tree over a list of leaf hashes.
+// Nodes hash the concatenation of their children, and a node without sibling
+// is promoted to the next layer as is.
+
+pub fn merkle_root<H: Hash>(leaves: &[H::Output]) -> H::Output {
+    if leaves.is_empty() {
+        return H::hash(&[]);
+    }
+    let mut layer = leaves.to_vec();
+    while layer.len() > 1 {
+        layer = next_layer::<H>(&layer);
+    }
+    layer[0]
+}
+
+// Sibling hashes from the leaf at `index` up to the root
+pub fn merkle_proof<H: Hash>(leaves: &[H::Output], mut index: usize) -> Vec<H::Output> {
+    let mut proof = Vec::new();
+    let mut layer = leaves.to_vec();
+    while layer.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < layer.len() {
+            proof.push(layer[sibling]);
+        }
+        index /= 2;
+        layer = next_layer::<H>(&layer);
+    }
+    proof
+}
+
+pub fn verify_proof<H: Hash>(
+    root: &H::Output,
+    leaf: H::Output,
+    mut index: usize,
+    leaf_count: usize,
+    proof: &[H::Output],
+) -> bool {
+    if index >= leaf_count {
+        return false;
+    }
+    let mut hash = leaf;
+    let mut siblings = proof.iter();
+    let mut len = leaf_count;
+    while len > 1 {
+        let sibling = index ^ 1;
+        if sibling < len {
+            hash = match siblings.next() {
+                Some(node) if index % 2 == 0 => H::hash_of(&(hash, *node)),
+                Some(node) => H::hash_of(&(*node, hash)),
+                None => return false,
+            };
+        }
+        index /= 2;
+        len = (len + 1) / 2;
+    }
+    siblings.next().is_none() && &hash == root
+}
+
+fn next_layer<H: Hash>(layer: &[H::Output]) -> Vec<H::Output> {
+    layer
+        .chunks(2)
+        .map(|pair| match pair.len() {
+            2 => H::hash_of(&(pair[0], pair[1])),
+            _ => pair[0],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_runtime::traits::BlakeTwo256;
+
+    fn leaves(count: u32) -> Vec<<BlakeTwo256 as Hash>::Output> {
+        (0..count).map(|i| BlakeTwo256::hash_of(&i)).collect()
+    }
+
+    #[test]
+    fn proofs_verify() {
+        for count in 1..10 {
+            let leaves = leaves(count);
+            let root = merkle_root::<BlakeTwo256>(&leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_proof::<BlakeTwo256>(&leaves, index);
+                assert!(verify_proof::<BlakeTwo256>(&root, *leaf, index, leaves.len(), &proof));
+            }
+        }
+    }
+
+    #[test]
+    fn proofs_reject_other_leaves() {
+        let leaves = leaves(5);
+        let root = merkle_root::<BlakeTwo256>(&leaves);
+        let proof = merkle_proof::<BlakeTwo256>(&leaves, 2);
+
+        assert!(!verify_proof::<BlakeTwo256>(&root, leaves[3], 2, leaves.len(), &proof));
+        assert!(!verify_proof::<BlakeTwo256>(&root, leaves[2], 3, leaves.len(), &proof));
+        assert!(!verify_proof::<BlakeTwo256>(&root, leaves[2], 2, leaves.len(), &proof[1..]));
+    }
+}
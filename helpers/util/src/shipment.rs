@@ -5,37 +5,117 @@ use frame_support::{
     sp_runtime::RuntimeDebug,
     // traits::EnsureOrigin,
 };
-use fixed::types::U16F16;
+use fixed::types::{I16F16, I32F32, I48F16};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_core::sr25519;
+use sp_std::prelude::*;
 use crate::catalog::ProductId;
 
 // General constraints to limit data size
 // Note: these could also be passed as trait config parameters
-pub const IDENTIFIER_MAX_LENGTH: usize = 10;
+pub const DEVICE_ID_MAX_LENGTH: usize = 36;
 pub const SHIPMENT_MAX_PRODUCTS: usize = 10;
+pub const CONTAINER_MAX_SHIPMENTS: usize = 20;
+// Coordinates are expressed in decimal degrees
+pub const LATITUDE_MAX: i32 = 90;
+pub const LONGITUDE_MAX: i32 = 180;
 
 // Custom types
 pub type Identifier = Vec<u8>;
-pub type Decimal = U16F16;
+// Signed, so that sub-zero readings and bounds (e.g. frozen goods) can be expressed
+pub type Decimal = I16F16;
+// Wide enough to sum many readings without overflowing
+pub type DecimalSum = I48F16;
+pub type Coordinate = I32F32;
 pub type ShipmentId = Identifier;
 pub type ShippingEventId = Identifier;
 pub type ShippingEventIndex = u64;
 pub type DeviceId = Identifier;
+pub type DevicePublicKey = sr25519::Public;
+pub type DeviceSignature = sr25519::Signature;
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 pub enum ShipmentStatus {
     Pending,
     InTransit,
     Delivered,
+    Cancelled,
+    Returned,
+    Lost,
+    Damaged,
+    Held,
+    // Delivered by the carrier, until the consignee confirms or the acknowledgment window expires
+    AwaitingAcknowledgment,
+}
+
+impl ShipmentStatus {
+    // The shipment lifecycle, as a single transition table.
+    // Returns the status a shipment moves to when an event of the given type is recorded,
+    // or `None` if the event is not allowed in the current status.
+    pub fn next(&self, event_type: &ShippingEventType) -> Option<ShipmentStatus> {
+        use ShipmentStatus::*;
+        use ShippingEventType::*;
+
+        match (self, event_type) {
+            // Sensor readings, custody handoffs and ownership transfers never change the status,
+            // but are refused once a shipment is closed
+            (status, SensorReading) | (status, CustodyHandoff) | (status, OwnershipTransfer)
+                if !status.is_final() => Some(status.clone()),
+            // Containers are unloaded at any stage, typically once delivered
+            (status, ShipmentDisaggregation) => Some(status.clone()),
+
+            (Pending, ShipmentPickup) => Some(InTransit),
+            (Pending, ShipmentCancellation) => Some(Cancelled),
+            (Pending, ShipmentHold) => Some(Held),
+
+            (InTransit, ShipmentDelivery) => Some(Delivered),
+            (InTransit, ShipmentReturn) => Some(Returned),
+            (InTransit, ShipmentLoss) => Some(Lost),
+            (InTransit, ShipmentDamage) => Some(Damaged),
+            (InTransit, ShipmentHold) => Some(Held),
+
+            // A held shipment is either released to a carrier or given up
+            (Held, ShipmentPickup) => Some(InTransit),
+            (Held, ShipmentCancellation) => Some(Cancelled),
+            (Held, ShipmentReturn) => Some(Returned),
+
+            // Damaged goods may still reach the consignee, or be sent back
+            (Damaged, ShipmentDelivery) => Some(Delivered),
+            (Damaged, ShipmentReturn) => Some(Returned),
+            (Damaged, ShipmentHold) => Some(Held),
+
+            _ => None,
+        }
+    }
+
+    // A shipment in a final status doesn't accept any further shipping event
+    pub fn is_final(&self) -> bool {
+        match self {
+            ShipmentStatus::Delivered
+            | ShipmentStatus::Cancelled
+            | ShipmentStatus::Returned
+            | ShipmentStatus::Lost => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
 pub struct Shipment<AccountId, Moment> {
     pub id: ShipmentId,
     pub owner: AccountId,
+    // Party physically holding the goods, the owner until a first custody handoff
+    pub custodian: AccountId,
+    // Party receiving the goods, who acknowledges their delivery, if any
+    pub consignee: Option<AccountId>,
     pub status: ShipmentStatus,
     pub products: Vec<ProductId>,
     pub registered: Moment,
     pub delivered: Option<Moment>,
+    // Number of sensor readings that fell outside the shipment's threshold rules
+    pub excursions: u32,
 }
 
 impl<AccountId, Moment> Shipment<AccountId, Moment> {
@@ -49,16 +129,62 @@ impl<AccountId, Moment> Shipment<AccountId, Moment> {
         self.delivered = Some(delivered_on);
         self
     }
+
+    pub fn update_status(mut self, status: ShipmentStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn hand_over(mut self, custodian: AccountId) -> Self {
+        self.custodian = custodian;
+        self
+    }
+
+    pub fn transfer(mut self, owner: AccountId) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    pub fn record_excursions(mut self, count: u32) -> Self {
+        self.excursions = self.excursions.saturating_add(count);
+        self
+    }
 }
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 pub enum ShippingEventType {
     ShipmentPickup,
     SensorReading,
     ShipmentDelivery,
+    ShipmentCancellation,
+    ShipmentReturn,
+    ShipmentLoss,
+    ShipmentDamage,
+    ShipmentHold,
+    CustodyHandoff,
+    ShipmentDisaggregation,
+    OwnershipTransfer,
+}
+
+impl ShippingEventType {
+    // Whether an event recorded on a container also applies to the shipments loaded in it
+    pub fn propagates_to_children(&self) -> bool {
+        match self {
+            ShippingEventType::ShipmentPickup
+            | ShippingEventType::SensorReading
+            | ShippingEventType::ShipmentDelivery
+            | ShippingEventType::ShipmentReturn
+            | ShippingEventType::ShipmentLoss
+            | ShippingEventType::ShipmentDamage
+            | ShippingEventType::ShipmentHold => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 pub struct ShippingEvent<Moment> {
     pub id: ShippingEventId,
     pub event_type: ShippingEventType,
@@ -69,12 +195,26 @@ pub struct ShippingEvent<Moment> {
 }
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 pub struct ReadPoint {
-    pub latitude: Decimal,
-    pub longitude: Decimal,
+    pub latitude: Coordinate,
+    pub longitude: Coordinate,
 }
 
-#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+impl ReadPoint {
+    pub fn has_valid_latitude(&self) -> bool {
+        let max = Coordinate::from_num(LATITUDE_MAX);
+        self.latitude >= -max && self.latitude <= max
+    }
+
+    pub fn has_valid_longitude(&self) -> bool {
+        let max = Coordinate::from_num(LONGITUDE_MAX);
+        self.longitude >= -max && self.longitude <= max
+    }
+}
+
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 pub enum ReadingType {
     Humidity,
     Pressure,
@@ -85,10 +225,107 @@ pub enum ReadingType {
 }
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 pub struct Reading<Moment> {
     pub device_id: DeviceId,
     pub reading_type: ReadingType,
     pub timestamp: Moment,
     pub value: Decimal,
+    // Signature of the device over the reading's signing payload
+    pub signature: DeviceSignature,
 }
 
+impl<Moment: Encode> Reading<Moment> {
+    // The payload a device signs: (shipment_id, reading_type, timestamp, value)
+    pub fn signing_payload(&self, shipment_id: &[u8]) -> Vec<u8> {
+        (shipment_id, &self.reading_type, &self.timestamp, &self.value).encode()
+    }
+}
+
+// A sensor device, registered by the organization operating it
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct Device<AccountId> {
+    pub id: DeviceId,
+    pub public_key: DevicePublicKey,
+    pub owner: AccountId,
+    pub revoked: bool,
+}
+
+
+// Acceptable range for the readings of a given type, e.g. a temperature range,
+// a maximum shock or a humidity ceiling. An unset bound is not checked.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ReadingThreshold {
+    pub min: Option<Decimal>,
+    pub max: Option<Decimal>,
+}
+
+impl ReadingThreshold {
+    // A threshold needs at least one bound, and bounds in the right order
+    pub fn is_valid(&self) -> bool {
+        match (self.min, self.max) {
+            (None, None) => false,
+            (Some(min), Some(max)) => min <= max,
+            _ => true,
+        }
+    }
+
+    pub fn is_excursion(&self, value: Decimal) -> bool {
+        self.min.map_or(false, |min| value < min) || self.max.map_or(false, |max| value > max)
+    }
+}
+
+// Running summary of the readings of a type recorded for a shipment
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ReadingStats<Moment> {
+    pub count: u32,
+    pub min: Decimal,
+    pub max: Decimal,
+    pub sum: DecimalSum,
+    // When the latest of the readings was taken
+    pub last_reading: Moment,
+}
+
+impl<Moment: Copy + Ord> ReadingStats<Moment> {
+    pub fn new(value: Decimal, timestamp: Moment) -> Self {
+        ReadingStats {
+            count: 1,
+            min: value,
+            max: value,
+            sum: DecimalSum::from_num(value),
+            last_reading: timestamp,
+        }
+    }
+
+    pub fn record(mut self, value: Decimal, timestamp: Moment) -> Self {
+        self.count = self.count.saturating_add(1);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum = self.sum.saturating_add(DecimalSum::from_num(value));
+        self.last_reading = self.last_reading.max(timestamp);
+        self
+    }
+
+    // The mean always lies between `min` and `max`, so it fits a `Decimal`
+    pub fn mean(&self) -> Decimal {
+        Decimal::from_num(self.sum / DecimalSum::from_num(self.count))
+    }
+}
+
+// A custody handoff between two parties, with the shipping event that recorded it
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct CustodyRecord<AccountId, Moment> {
+    pub from: AccountId,
+    pub to: AccountId,
+    pub event_idx: ShippingEventIndex,
+    pub timestamp: Moment,
+}
+
+// A payment held in escrow, paid to the carrier once the shipment is delivered
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct Payment<AccountId, Balance> {
+    pub payer: AccountId,
+    pub carrier: AccountId,
+    pub amount: Balance,
+}
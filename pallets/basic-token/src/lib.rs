@@ -4,6 +4,8 @@
 //! 1. set total supply
 //! 2. establish ownership upon configuration of circulating tokens
 //! 3. coordinate token transfers with the runtime functions
+//! 4. lock tokens in escrow for other pallets, through the `Escrow` trait
+use account_set::Escrow;
 use frame_support::{
 	decl_error, decl_event, decl_module, decl_storage, dispatch::DispatchResult, ensure,
 };
@@ -20,6 +22,9 @@ decl_storage! {
 	trait Store for Module<T: Trait> as Token {
 		pub Balances get(fn get_balance): map hasher(blake2_128_concat) T::AccountId => u64;
 
+		/// Tokens set aside in escrow, not spendable until released or refunded
+		pub LockedBalances get(fn locked_balance): map hasher(blake2_128_concat) T::AccountId => u64;
+
 		pub TotalSupply get(fn total_supply): u64 = 21000000;
 
 		Init get(fn is_init): bool;
@@ -35,6 +40,12 @@ decl_event!(
 		Initialized(AccountId),
 		/// Tokens successfully transferred between users
 		Transfer(AccountId, AccountId, u64), // (from, to, value)
+		/// Tokens were set aside in escrow
+		Locked(AccountId, u64),
+		/// Tokens in escrow were paid to another user
+		Released(AccountId, AccountId, u64), // (from, to, value)
+		/// Tokens in escrow were returned to their owner
+		Refunded(AccountId, u64),
 	}
 );
 
@@ -91,3 +102,40 @@ decl_module! {
 		}
 	}
 }
+
+impl<T: Trait> Escrow for Module<T> {
+	type AccountId = T::AccountId;
+	type Balance = u64;
+
+	fn lock(who: &T::AccountId, amount: u64) -> bool {
+		let balance = match Self::get_balance(who).checked_sub(amount) {
+			Some(balance) => balance,
+			None => return false,
+		};
+
+		<Balances<T>>::insert(who, balance);
+		<LockedBalances<T>>::mutate(who, |locked| *locked += amount); // entire supply fits in u64
+
+		Self::deposit_event(RawEvent::Locked(who.clone(), amount));
+		true
+	}
+
+	fn release(from: &T::AccountId, to: &T::AccountId, amount: u64) {
+		// Never pay out more than was locked
+		let amount = amount.min(Self::locked_balance(from));
+
+		<LockedBalances<T>>::mutate(from, |locked| *locked -= amount);
+		<Balances<T>>::mutate(to, |balance| *balance += amount);
+
+		Self::deposit_event(RawEvent::Released(from.clone(), to.clone(), amount));
+	}
+
+	fn refund(who: &T::AccountId, amount: u64) {
+		let amount = amount.min(Self::locked_balance(who));
+
+		<LockedBalances<T>>::mutate(who, |locked| *locked -= amount);
+		<Balances<T>>::mutate(who, |balance| *balance += amount);
+
+		Self::deposit_event(RawEvent::Refunded(who.clone(), amount));
+	}
+}
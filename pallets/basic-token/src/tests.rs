@@ -1,4 +1,5 @@
 use crate::{Error, Module, Trait};
+use account_set::Escrow;
 use frame_support::{assert_noop, assert_ok, impl_outer_origin, parameter_types, impl_outer_event};
 use frame_system as system;
 use sp_core::H256;
@@ -171,3 +172,53 @@ fn members_can_call() {
 		// assert!(System::events().iter().any(|a| a.event == expected_event));
 	})
 }
+
+#[test]
+fn lock_and_release_works() {
+	ExtBuilder::build().execute_with(|| {
+		assert_ok!(BasicToken::init(Origin::signed(1)));
+
+		assert!(BasicToken::lock(&1, 100));
+		assert_eq!(BasicToken::get_balance(1), 20999900);
+		assert_eq!(BasicToken::locked_balance(1), 100);
+
+		BasicToken::release(&1, &2, 100);
+		assert_eq!(BasicToken::locked_balance(1), 0);
+		assert_eq!(BasicToken::get_balance(2), 100);
+	})
+}
+
+#[test]
+fn lock_and_refund_works() {
+	ExtBuilder::build().execute_with(|| {
+		assert_ok!(BasicToken::init(Origin::signed(1)));
+
+		assert!(BasicToken::lock(&1, 100));
+		BasicToken::refund(&1, 100);
+		assert_eq!(BasicToken::locked_balance(1), 0);
+		assert_eq!(BasicToken::get_balance(1), 21000000);
+	})
+}
+
+#[test]
+fn cant_lock_more_than_you_have() {
+	ExtBuilder::build().execute_with(|| {
+		assert_ok!(BasicToken::init(Origin::signed(1)));
+
+		assert!(!BasicToken::lock(&1, 21000001));
+		assert_eq!(BasicToken::get_balance(1), 21000000);
+		assert_eq!(BasicToken::locked_balance(1), 0);
+	})
+}
+
+#[test]
+fn cant_release_more_than_locked() {
+	ExtBuilder::build().execute_with(|| {
+		assert_ok!(BasicToken::init(Origin::signed(1)));
+
+		assert!(BasicToken::lock(&1, 100));
+		BasicToken::release(&1, &2, 1000);
+		assert_eq!(BasicToken::get_balance(2), 100);
+		assert_eq!(BasicToken::get_balance(1), 20999900);
+	})
+}
@@ -1,3 +1,91 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
+//! Pallet that lets an organization account delegate the right to act on its behalf to other
+//! accounts. Delegations are exposed to other pallets through the `RelationSet` trait, where an
+//! organization is the `parent` and its delegate the `child` of a `Relation`.
+
 pub mod procs;
+use frame_support::{decl_error, decl_event, decl_module, decl_storage, dispatch::DispatchResult, ensure};
+use frame_system::{self as system, ensure_signed};
+use account_set::{Relation, RelationSet};
+
+#[cfg(test)]
+mod tests;
+
+/// The pallet's configuration trait
+pub trait Trait: system::Trait {
+	/// The ubiquitous event type
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Delegator {
+		// Delegations keyed by (organization, delegate).
+		// We map to '()' for the same reason the membership maps do: only the key matters.
+		pub Delegations get(fn delegations): double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) T::AccountId => ();
+	}
+}
+
+decl_event!(
+	pub enum Event<T>
+	where
+		AccountId = <T as system::Trait>::AccountId,
+	{
+		/// An organization delegated to an account. Parameters are (organization, delegate)
+		DelegateAdded(AccountId, AccountId),
+		/// An organization revoked a delegation. Parameters are (organization, delegate)
+		DelegateRemoved(AccountId, AccountId),
+	}
+);
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// The account is already a delegate of the organization
+		AlreadyDelegate,
+		/// The account is not a delegate of the organization
+		NotDelegate,
+		/// An organization cannot delegate to itself
+		SelfDelegation,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		type Error = Error<T>;
+
+		/// Allows `delegate` to act on behalf of the calling organization
+		#[weight = 10_000]
+		pub fn add_delegate(origin, delegate: T::AccountId) -> DispatchResult {
+			let org = ensure_signed(origin)?;
+
+			ensure!(org != delegate, Error::<T>::SelfDelegation);
+			ensure!(!Delegations::<T>::contains_key(&org, &delegate), Error::<T>::AlreadyDelegate);
+
+			Delegations::<T>::insert(&org, &delegate, ());
+			Self::deposit_event(RawEvent::DelegateAdded(org, delegate));
+			Ok(())
+		}
+
+		/// Revokes a delegation previously granted by the calling organization
+		#[weight = 10_000]
+		pub fn remove_delegate(origin, delegate: T::AccountId) -> DispatchResult {
+			let org = ensure_signed(origin)?;
+
+			ensure!(Delegations::<T>::contains_key(&org, &delegate), Error::<T>::NotDelegate);
+
+			Delegations::<T>::remove(&org, &delegate);
+			Self::deposit_event(RawEvent::DelegateRemoved(org, delegate));
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> RelationSet for Module<T> {
+	type OrgId = T::AccountId;
+
+	fn contains(relation: &Relation<T::AccountId>) -> bool {
+		Delegations::<T>::contains_key(&relation.parent, &relation.child)
+	}
+}
@@ -0,0 +1,141 @@
+use crate::*;
+use frame_support::{assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types};
+use frame_system as system;
+use sp_core::H256;
+use sp_io::TestExternalities;
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+	Perbill,
+};
+
+impl_outer_origin! {
+	pub enum Origin for TestRuntime {}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TestRuntime;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: u32 = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+}
+impl system::Trait for TestRuntime {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Index = u64;
+	type Call = ();
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type ModuleToIndex = ();
+	type AccountData = ();
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+mod delegator {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for TestRuntime {
+		system<T>,
+		delegator<T>,
+	}
+}
+
+impl Trait for TestRuntime {
+	type Event = TestEvent;
+}
+
+pub type System = system::Module<TestRuntime>;
+pub type Delegator = Module<TestRuntime>;
+
+pub struct ExtBuilder;
+
+impl ExtBuilder {
+	pub fn build() -> TestExternalities {
+		let storage = system::GenesisConfig::default()
+			.build_storage::<TestRuntime>()
+			.unwrap();
+		let mut ext = TestExternalities::from(storage);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
+
+#[test]
+fn add_delegate_works() {
+	ExtBuilder::build().execute_with(|| {
+		assert_ok!(Delegator::add_delegate(Origin::signed(1), 2));
+
+		let expected_event = TestEvent::delegator(RawEvent::DelegateAdded(1, 2));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		assert!(<Delegator as RelationSet>::contains(&Relation::new(1, 2)));
+		// Delegations are directed
+		assert!(!<Delegator as RelationSet>::contains(&Relation::new(2, 1)));
+	})
+}
+
+#[test]
+fn cant_delegate_twice() {
+	ExtBuilder::build().execute_with(|| {
+		assert_ok!(Delegator::add_delegate(Origin::signed(1), 2));
+
+		assert_noop!(
+			Delegator::add_delegate(Origin::signed(1), 2),
+			Error::<TestRuntime>::AlreadyDelegate
+		);
+	})
+}
+
+#[test]
+fn cant_delegate_to_self() {
+	ExtBuilder::build().execute_with(|| {
+		assert_noop!(
+			Delegator::add_delegate(Origin::signed(1), 1),
+			Error::<TestRuntime>::SelfDelegation
+		);
+	})
+}
+
+#[test]
+fn remove_delegate_works() {
+	ExtBuilder::build().execute_with(|| {
+		assert_ok!(Delegator::add_delegate(Origin::signed(1), 2));
+		assert_ok!(Delegator::remove_delegate(Origin::signed(1), 2));
+
+		let expected_event = TestEvent::delegator(RawEvent::DelegateRemoved(1, 2));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		assert!(!<Delegator as RelationSet>::contains(&Relation::new(1, 2)));
+	})
+}
+
+#[test]
+fn cant_remove_unknown_delegate() {
+	ExtBuilder::build().execute_with(|| {
+		assert_noop!(
+			Delegator::remove_delegate(Origin::signed(1), 2),
+			Error::<TestRuntime>::NotDelegate
+		);
+	})
+}
@@ -5,6 +5,7 @@
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
+use account_set::ProductRegistry;
 use frame_support::{
 	codec::{Decode, Encode},
 	decl_error, decl_event, decl_module, decl_storage,
@@ -14,6 +15,8 @@ use frame_support::{
 };
 use frame_system::{self as system, ensure_signed};
 
+use bluefn_util::gs1::IdentifierValidator;
+
 #[cfg(test)]
 mod tests;
 
@@ -80,6 +83,8 @@ impl ProductProperty {
 
 pub trait Trait: system::Trait + timestamp::Trait  {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+	/// Checks the structure of product IDs, e.g. `bluefn_util::gs1::Gtin` for GTINs
+	type ProductIdValidator: IdentifierValidator;
 	// type CreateRoleOrigin: EnsureOrigin<Self::Origin>;
 }
 
@@ -114,6 +119,8 @@ decl_event!(
 
 		/// Products
 		ProductRegistered(AccountId, ProductId, AccountId),
+		/// A product was deregistered by its owner, new shipments can't carry it anymore
+		ProductDeregistered(AccountId, ProductId),
 	}
 );
 
@@ -129,9 +136,15 @@ decl_error! {
 		ProductIdMissing,
         ProductIdTooLong,
         ProductIdExists,
+        /// The product ID is malformed, e.g. its check digit doesn't match
+        ProductIdInvalid,
         ProductTooManyProps,
         ProductInvalidPropName,
         ProductInvalidPropValue,
+        /// No product is registered with this ID
+        ProductIsUnknown,
+        /// Only the product's owner can deregister it
+        SenderIsNotProductOwner,
 	}
 }
 
@@ -234,6 +247,38 @@ decl_module! {
 
             Ok(())
         }
+
+        /// Removes a product from the registry
+        #[weight = 10_000]
+        pub fn deregister_product(origin, id: ProductId) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+
+            // Check product exists and sender owns it (1 DB read)
+            let owner = <OwnerOf<T>>::get(&id).ok_or(Error::<T>::ProductIsUnknown)?;
+            ensure!(owner == who, Error::<T>::SenderIsNotProductOwner);
+
+            // Remove product & ownerOf (3 DB writes)
+            <Products<T>>::remove(&id);
+            <ProductsOfOrganization<T>>::mutate(&owner, |products| products.retain(|p| p != &id));
+            <OwnerOf<T>>::remove(&id);
+
+            Self::deposit_event(RawEvent::ProductDeregistered(who, id));
+
+            Ok(())
+        }
+	}
+}
+
+impl<T: Trait> ProductRegistry for Module<T> {
+	type ProductId = ProductId;
+	type AccountId = T::AccountId;
+
+	fn contains(id: &ProductId) -> bool {
+		<Products<T>>::contains_key(id)
+	}
+
+	fn owner_of(id: &ProductId) -> Option<T::AccountId> {
+		<OwnerOf<T>>::get(id)
 	}
 }
 
@@ -251,6 +296,7 @@ impl<T: Trait> Module<T> {
             id.len() <= PRODUCT_ID_MAX_LENGTH,
             Error::<T>::ProductIdTooLong
         );
+		ensure!(T::ProductIdValidator::is_valid(id), Error::<T>::ProductIdInvalid);
 		Ok(())
 	}
 
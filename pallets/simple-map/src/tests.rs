@@ -13,6 +13,8 @@ use sp_runtime::{
 };
 use core::marker::PhantomData;
 
+use bluefn_util::gs1::Gtin;
+
 // Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct TestRuntime;
@@ -80,6 +82,7 @@ impl timestamp::Trait for TestRuntime {
 pub struct MockOrigin<T>(PhantomData<T>);
 impl Trait for TestRuntime {
 	type Event = TestEvent;
+	type ProductIdValidator = Gtin;
 	// type CreateRoleOrigin = MockOrigin<TestRuntime>;
 }
 
@@ -314,4 +317,51 @@ fn create_product_with_invalid_sender() {
             dispatch::DispatchError::BadOrigin
         );
 	});
-}
\ No newline at end of file
+}
+
+#[test]
+fn deregister_product_works() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = 1;
+		let id = TEST_PRODUCT_ID.as_bytes().to_owned();
+		assert_ok!(SimpleMap::register_product(Origin::signed(owner), id.clone(), owner, None));
+		assert!(<SimpleMap as ProductRegistry>::contains(&id));
+		assert_eq!(<SimpleMap as ProductRegistry>::owner_of(&id), Some(owner));
+
+		assert_ok!(SimpleMap::deregister_product(Origin::signed(owner), id.clone()));
+
+		assert!(!<SimpleMap as ProductRegistry>::contains(&id));
+		assert_eq!(<SimpleMap as ProductRegistry>::owner_of(&id), None);
+		assert_eq!(SimpleMap::owner_of(&id), None);
+		assert_eq!(<ProductsOfOrganization<TestRuntime>>::get(owner), Vec::<ProductId>::new());
+		let expected_event = TestEvent::simple_map(RawEvent::ProductDeregistered(owner, id));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	});
+}
+
+#[test]
+fn deregister_product_as_non_owner() {
+	ExtBuilder::build().execute_with(|| {
+		let id = TEST_PRODUCT_ID.as_bytes().to_owned();
+		assert_ok!(SimpleMap::register_product(Origin::signed(1), id.clone(), 1, None));
+
+		assert_noop!(
+			SimpleMap::deregister_product(Origin::signed(2), id.clone()),
+			Error::<TestRuntime>::SenderIsNotProductOwner
+		);
+		assert_noop!(
+			SimpleMap::deregister_product(Origin::signed(1), b"00012345600029".to_vec()),
+			Error::<TestRuntime>::ProductIsUnknown
+		);
+	});
+}
+
+#[test]
+fn create_product_with_invalid_check_digit() {
+	ExtBuilder::build().execute_with(|| {
+		assert_noop!(
+			SimpleMap::register_product(Origin::signed(1), b"00012345600013".to_vec(), 1, None),
+			Error::<TestRuntime>::ProductIdInvalid
+		);
+	});
+}
@@ -0,0 +1,43 @@
+//! Runtime API definition for the tracks pallet.
+//!
+//! Runtimes implement it by forwarding to the query helpers of `tracks::Module`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use parity_scale_codec::Codec;
+use sp_std::prelude::*;
+
+use bluefn_util::shipment::{
+	Decimal, ReadingType, Shipment, ShipmentId, ShipmentStatus, ShippingEvent,
+};
+
+sp_api::decl_runtime_apis! {
+	pub trait TracksApi<AccountId, Moment>
+	where
+		AccountId: Codec,
+		Moment: Codec,
+	{
+		/// Shipments of an organization, optionally only those in `status`.
+		/// The organization's index is scanned from `start` until `limit` shipments are found.
+		/// Returns them, with the index to resume from if the scan stopped early.
+		fn shipments_of_org(
+			owner: AccountId,
+			status: Option<ShipmentStatus>,
+			start: u32,
+			limit: u32,
+		) -> (Vec<Shipment<AccountId, Moment>>, Option<u32>);
+
+		/// Events recorded for a shipment, in the order they were recorded.
+		/// Pruned events are left out.
+		fn shipment_timeline(shipment_id: ShipmentId) -> Vec<ShippingEvent<Moment>>;
+
+		/// Readings of a type recorded for a shipment, taken from `from` to `to` included,
+		/// as (reading timestamp, value)
+		fn reading_series(
+			shipment_id: ShipmentId,
+			reading_type: ReadingType,
+			from: Moment,
+			to: Moment,
+		) -> Vec<(Moment, Decimal)>;
+	}
+}
@@ -0,0 +1,383 @@
+//! Recording of shipping events.
+//!
+//! Events are validated against the state they leave behind in memory, and storage is only
+//! written when the batch is committed. A shipment is thus read and written once, however many
+//! of its events are recorded, and a batch that isn't committed leaves no trace.
+
+use frame_support::ensure;
+use sp_std::collections::{btree_map::BTreeMap, btree_set::BTreeSet};
+use sp_std::prelude::*;
+
+use bluefn_util::shipment::*;
+
+use crate::{
+	AllEvents, ChildrenOf, Error, Event, EventCount, EventCountOfShipment, EventIndices, EventsOfShipment, Members,
+	Module, ParentOf, RawEvent, ReadingStatistics, Role, Shipments, Trait,
+};
+
+// A shipment touched by the batch
+struct PendingShipment<T: Trait> {
+	shipment: Shipment<T::AccountId, T::Moment>,
+	// Whether the shipment itself must be written
+	changed: bool,
+	// Whether the shipment was delivered by the batch, so its events must be pruned in time
+	delivered: bool,
+	// Whether the batch recorded a delivery the consignee must acknowledge
+	awaiting_acknowledgment: bool,
+	// Events of the shipment in storage, and those recorded by the batch
+	stored_events: u32,
+	new_events: Vec<ShippingEventIndex>,
+	latest_event: Option<T::Moment>,
+	// Statistics of the reading types recorded by the batch, including stored readings
+	reading_stats: Vec<(ReadingType, ReadingStats<T::Moment>)>,
+	// Shipments loaded in the shipment, if it's a container
+	children: Vec<ShipmentId>,
+	// Whether the shipment carries a recalled product
+	recalled: bool,
+}
+
+impl<T: Trait> PendingShipment<T> {
+	fn event_count(&self) -> u32 {
+		self.stored_events + self.new_events.len() as u32
+	}
+
+	fn push_event(&mut self, event_idx: ShippingEventIndex, timestamp: T::Moment) {
+		self.new_events.push(event_idx);
+		self.latest_event = Some(timestamp);
+	}
+
+	// Folds readings into the shipment's statistics, read on first use (1 DB read per type)
+	fn record_readings(&mut self, shipment_id: &[u8], readings: &[(ReadingType, Decimal, T::Moment)]) {
+		for (reading_type, value, timestamp) in readings.iter() {
+			match self.reading_stats.iter_mut().find(|(other, _)| other == reading_type) {
+				Some((_, stats)) => *stats = stats.clone().record(*value, *timestamp),
+				None => {
+					let stats = match <ReadingStatistics<T>>::get(shipment_id, reading_type) {
+						Some(stats) => stats.record(*value, *timestamp),
+						None => ReadingStats::new(*value, *timestamp),
+					};
+					self.reading_stats.push((*reading_type, stats));
+				}
+			}
+		}
+	}
+}
+
+// An event of the batch, as applied to its shipment and the shipments loaded in it
+struct RecordedEvent<T: Trait> {
+	idx: ShippingEventIndex,
+	id: ShippingEventId,
+	timestamp: T::Moment,
+	readings: Vec<(ReadingType, Decimal, T::Moment)>,
+}
+
+pub struct EventBatch<T: Trait> {
+	who: T::AccountId,
+	// Time the batch is recorded at
+	now: T::Moment,
+	// Roles of the sender, scoping the events it may record
+	roles: Vec<Role>,
+	event_count: ShippingEventIndex,
+	events: Vec<(ShippingEventIndex, ShippingEvent<T::Moment>)>,
+	event_ids: BTreeSet<ShippingEventId>,
+	shipments: BTreeMap<ShipmentId, PendingShipment<T>>,
+	// Containers unloaded by the batch
+	unloaded: Vec<(ShipmentId, Vec<ShipmentId>)>,
+	deposits: Vec<Event<T>>,
+}
+
+impl<T: Trait> EventBatch<T> {
+	pub fn new(who: T::AccountId) -> Self {
+		Self::at(who, <timestamp::Module<T>>::now())
+	}
+
+	/// A batch recorded at the given time, such as genesis events at their timestamp
+	pub fn at(who: T::AccountId, now: T::Moment) -> Self {
+		EventBatch {
+			roles: Members::<T>::get(&who),
+			who,
+			now,
+			event_count: EventCount::get(),
+			events: Vec::new(),
+			event_ids: BTreeSet::new(),
+			shipments: BTreeMap::new(),
+			unloaded: Vec::new(),
+			deposits: Vec::new(),
+		}
+	}
+
+	/// Number of events recorded so far
+	pub fn len(&self) -> usize {
+		self.events.len()
+	}
+
+	// Reads a shipment on first use (3 DB reads, plus 2 for its latest event and 1 per product)
+	fn load(&mut self, shipment_id: &[u8]) -> Result<&mut PendingShipment<T>, Error<T>> {
+		if !self.shipments.contains_key(shipment_id) {
+			let shipment = <Shipments<T>>::get(shipment_id).ok_or(Error::<T>::ShipmentIsUnknown)?;
+			let pending = PendingShipment {
+				changed: false,
+				delivered: false,
+				awaiting_acknowledgment: false,
+				stored_events: EventCountOfShipment::get(shipment_id),
+				new_events: Vec::new(),
+				latest_event: Module::<T>::latest_event_timestamp(shipment_id),
+				reading_stats: Vec::new(),
+				children: ChildrenOf::get(shipment_id),
+				recalled: Module::<T>::carries_recalled_product(&shipment.products),
+				shipment,
+			};
+			self.shipments.insert(shipment_id.to_vec(), pending);
+		}
+		Ok(self.shipments.get_mut(shipment_id).expect("inserted above; qed"))
+	}
+
+	/// Validates a shipping event against storage and the events already in the batch,
+	/// then adds it to the batch. Nothing changes when the event is refused.
+	/// Returns the index the event will be stored at.
+	pub fn record(&mut self, event: ShippingEvent<T::Moment>) -> Result<ShippingEventIndex, Error<T>> {
+		// Validate extrinsic data (no storage access)
+		// -----------------------
+		// Check the sender may record this type of event
+		Module::<T>::validate_granted_roles(&self.roles, Role::recording(&event.event_type))?;
+		// Validate format of event & shipment ID
+		Module::<T>::validate_identifier(&event.id)?;
+		Module::<T>::validate_identifier(&event.shipment_id)?;
+		// Validate event location, if any
+		Module::<T>::validate_read_point(&event.location)?;
+
+		let event_id = event.id.clone();
+		let event_type = event.event_type.clone();
+		let shipment_id = event.shipment_id.clone();
+
+		// Storage checks
+		// --------------
+		let event_idx = self.event_count.checked_add(1).ok_or(Error::<T>::ShippingEventMaxExceeded)?;
+		// Check event doesn't exist yet (1 DB read)
+		ensure!(!self.event_ids.contains(&event_id), Error::<T>::ShippingEventAlreadyExists);
+		Module::<T>::validate_new_shipping_event(&event_id)?;
+
+		// Check shipment is known
+		let pending = self.load(&shipment_id)?;
+		// Additionnally, we refuse shipping events the shipment's status doesn't allow
+		let new_status = Module::<T>::validate_status_transition(&pending.shipment.status, &event_type)?;
+		// Shipments carrying a recalled product stay where they are
+		Module::<T>::validate_recall(pending.recalled, &pending.shipment.status, &new_status, &event_type)?;
+		// Check the shipment's event index isn't full
+		Module::<T>::validate_event_capacity(pending.event_count())?;
+		// Check the event and its readings are dated consistently
+		Module::<T>::validate_event_timing(self.now, pending.shipment.registered, pending.latest_event, &event)?;
+		let children = pending.children.clone();
+
+		// Check readings were signed by registered devices (1 DB read per reading)
+		Module::<T>::validate_readings(&shipment_id, &event.readings)?;
+
+		// Evaluate readings against the shipment's threshold rules (1 DB read per reading)
+		let excursions = Module::<T>::find_excursions(&shipment_id, &event.readings);
+
+		// Check deliveries happen within the shipment's geofence, if any (1 DB read)
+		let outside_geofence = match event_type {
+			ShippingEventType::ShipmentDelivery => Module::<T>::validate_delivery_location(&shipment_id, &event.location)?,
+			_ => false,
+		};
+
+		// Shipments loaded in a container follow it (1 DB read per shipment and reading)
+		let mut child_updates = Vec::new();
+		if event_type.propagates_to_children() {
+			for child_id in children.iter() {
+				let child = self.load(child_id)?;
+				// Readings keep flowing for the other shipments once one has been closed
+				if event_type == ShippingEventType::SensorReading && child.shipment.status.is_final() {
+					continue;
+				}
+				let child_status = child.shipment.status.next(&event_type).ok_or(Error::<T>::ChildShipmentTransitionNotAllowed)?;
+				Module::<T>::validate_recall(child.recalled, &child.shipment.status, &child_status, &event_type)?;
+				Module::<T>::validate_event_capacity(child.event_count())?;
+				ensure!(event.timestamp >= child.shipment.registered, Error::<T>::ShippingEventBeforeRegistration);
+				Module::<T>::validate_event_order(child.latest_event, event.timestamp)?;
+				let child_excursions = Module::<T>::find_excursions(child_id, &event.readings);
+				child_updates.push((child_id.clone(), child_status, child_excursions));
+			}
+		}
+		let disaggregation = event_type == ShippingEventType::ShipmentDisaggregation;
+		if disaggregation {
+			for child_id in children.iter() {
+				let child = self.load(child_id)?;
+				Module::<T>::validate_event_capacity(child.event_count())?;
+				Module::<T>::validate_event_order(child.latest_event, event.timestamp)?;
+			}
+		}
+
+		// Batch updates
+		// -------------
+		let timestamp = event.timestamp;
+		let recorded = RecordedEvent::<T> {
+			idx: event_idx,
+			id: event_id.clone(),
+			timestamp,
+			readings: event
+				.readings
+				.iter()
+				.map(|reading| (reading.reading_type, reading.value, reading.timestamp))
+				.collect(),
+		};
+		self.event_count = event_idx;
+		self.events.push((event_idx, event));
+		self.event_ids.insert(event_id.clone());
+
+		self.deposits.push(RawEvent::ShippingEventRecorded(
+			self.who.clone(),
+			event_id.clone(),
+			shipment_id.clone(),
+			event_type.clone(),
+		));
+		if outside_geofence {
+			self.deposits.push(RawEvent::DeliveryOutsideGeofence(shipment_id.clone(), event_id.clone()));
+		}
+		self.apply(&shipment_id, &recorded, new_status, excursions);
+
+		for (child_id, child_status, child_excursions) in child_updates {
+			self.apply(&child_id, &recorded, child_status, child_excursions);
+		}
+		if disaggregation {
+			for child_id in children.iter() {
+				if let Some(child) = self.shipments.get_mut(child_id) {
+					child.push_event(event_idx, timestamp);
+				}
+			}
+			if let Some(container) = self.shipments.get_mut(&shipment_id) {
+				container.children.clear();
+			}
+			self.deposits.push(RawEvent::ShipmentsDisaggregated(shipment_id.clone(), children.clone()));
+			self.unloaded.push((shipment_id, children));
+		}
+
+		Ok(event_idx)
+	}
+
+	/// Hands a shipment of the batch over to a new custodian
+	pub fn hand_over(&mut self, shipment_id: &[u8], custodian: T::AccountId) {
+		if let Some(pending) = self.shipments.get_mut(shipment_id) {
+			pending.shipment = pending.shipment.clone().hand_over(custodian);
+			pending.changed = true;
+		}
+	}
+
+	/// Transfers a shipment of the batch to a new owner
+	pub fn transfer(&mut self, shipment_id: &[u8], owner: T::AccountId) {
+		if let Some(pending) = self.shipments.get_mut(shipment_id) {
+			pending.shipment = pending.shipment.clone().transfer(owner);
+			pending.changed = true;
+		}
+	}
+
+	// Updates a shipment's events, status, readings and excursion counter after one of its
+	// events was recorded
+	fn apply(
+		&mut self,
+		shipment_id: &[u8],
+		event: &RecordedEvent<T>,
+		new_status: ShipmentStatus,
+		excursions: Vec<(ReadingType, Decimal)>,
+	) {
+		let now = self.now;
+		let pending = match self.shipments.get_mut(shipment_id) {
+			Some(pending) => pending,
+			None => return,
+		};
+		pending.push_event(event.idx, event.timestamp);
+		pending.record_readings(shipment_id, &event.readings);
+
+		// Deliveries to a consignee only complete once acknowledged
+		let new_status = match new_status {
+			ShipmentStatus::Delivered if pending.shipment.consignee.is_some() => ShipmentStatus::AwaitingAcknowledgment,
+			status => status,
+		};
+		let status_changed = new_status != pending.shipment.status;
+		if status_changed || !excursions.is_empty() {
+			let shipment = pending.shipment.clone();
+			let shipment = match new_status {
+				ShipmentStatus::Delivered if status_changed => {
+					pending.delivered = true;
+					shipment.deliver(now)
+				}
+				ShipmentStatus::AwaitingAcknowledgment if status_changed => {
+					pending.awaiting_acknowledgment = true;
+					shipment.update_status(new_status.clone())
+				}
+				_ => shipment.update_status(new_status.clone()),
+			};
+			pending.shipment = shipment.record_excursions(excursions.len() as u32);
+			pending.changed = true;
+		}
+
+		for (reading_type, value) in excursions {
+			self.deposits.push(RawEvent::ThresholdExcursion(shipment_id.to_vec(), event.id.clone(), reading_type, value));
+		}
+		if status_changed {
+			self.deposits.push(RawEvent::ShipmentStatusUpdated(shipment_id.to_vec(), new_status));
+		}
+	}
+
+	/// Writes the batch to storage, settles the payments of the shipments it delivered
+	/// or closed otherwise, and deposits its events
+	pub fn commit(mut self) {
+		if self.events.is_empty() {
+			return;
+		}
+
+		// Storage writes
+		// --------------
+		// Events (1 DB write, plus 2 per event)
+		EventCount::put(self.event_count);
+		for (event_idx, event) in self.events {
+			EventIndices::insert(&event.id, event_idx);
+			<AllEvents<T>>::insert(event_idx, event);
+		}
+
+		// Shipments (up to 2 DB writes per shipment, plus 1 per event and 1 per delivery)
+		for (shipment_id, pending) in self.shipments {
+			for (offset, event_idx) in pending.new_events.iter().enumerate() {
+				EventsOfShipment::insert(&shipment_id, pending.stored_events + offset as u32, event_idx);
+			}
+			if !pending.new_events.is_empty() {
+				EventCountOfShipment::insert(&shipment_id, pending.stored_events + pending.new_events.len() as u32);
+			}
+			// Reading statistics (1 DB write per reading type)
+			for (reading_type, stats) in pending.reading_stats {
+				<ReadingStatistics<T>>::insert(&shipment_id, reading_type, stats);
+			}
+			if pending.delivered {
+				Module::<T>::schedule_pruning(&shipment_id, self.now);
+				Module::<T>::record_lateness(&shipment_id, self.now);
+			}
+			if pending.awaiting_acknowledgment {
+				Module::<T>::await_acknowledgment(&shipment_id, self.now);
+			}
+			if pending.changed {
+				// Payments (up to 3 DB writes)
+				let settlement = match pending.shipment.status {
+					ShipmentStatus::Delivered if pending.delivered => Module::<T>::settle_payment(&shipment_id, true),
+					// Shipments closed without a delivery are refunded
+					ShipmentStatus::Cancelled | ShipmentStatus::Returned | ShipmentStatus::Lost => {
+						Module::<T>::settle_payment(&shipment_id, false)
+					}
+					_ => None,
+				};
+				self.deposits.extend(settlement);
+				<Shipments<T>>::insert(&shipment_id, pending.shipment);
+			}
+		}
+
+		for (container_id, children) in self.unloaded {
+			for child_id in children.iter() {
+				ParentOf::remove(child_id);
+			}
+			ChildrenOf::remove(&container_id);
+		}
+
+		for event in self.deposits {
+			Module::<T>::deposit_event(event);
+		}
+	}
+}
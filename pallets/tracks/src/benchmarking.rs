@@ -0,0 +1,358 @@
+//! Benchmarks of the tracks pallet.
+//!
+//! Calls are measured in their worst case: shipping events are deliveries checked against
+//! a polygon geofence, whose readings all fall outside a threshold rule, and reach the
+//! shipments loaded in the container they're recorded on. Shipments carry the most products
+//! and are paid on delivery.
+
+use frame_benchmarking::{account, benchmarks, whitelisted_caller};
+use frame_support::traits::EnsureOrigin;
+use frame_system::RawOrigin;
+use sp_runtime::KeyTypeId;
+use sp_std::prelude::*;
+
+use bluefn_util::catalog::ProductId;
+use bluefn_util::geofence::Geofence;
+use bluefn_util::shipment::*;
+
+use crate::*;
+
+// Device keys are generated in the keystore, under this key type
+const DEVICE_KEY_TYPE: KeyTypeId = KeyTypeId(*b"trdv");
+const DEVICE_ID: &[u8] = b"benchmark-device";
+// A valid SSCC, in case the runtime checks the structure of shipment IDs
+const SHIPMENT_ID: &[u8] = b"106141412345678908";
+// Upper bound of the readings carried by a benchmarked event
+const MAX_READINGS: u32 = 20;
+// Upper bound of the deadlines already queued in the bucket of a registered shipment
+const MAX_QUEUED_DEADLINES: u32 = 1_000;
+
+fn moment<T: Trait>(ms: u32) -> T::Moment {
+	ms.into()
+}
+
+fn read_point(latitude: i32, longitude: i32) -> ReadPoint {
+	ReadPoint {
+		latitude: Coordinate::from_num(latitude),
+		longitude: Coordinate::from_num(longitude),
+	}
+}
+
+// An octagonal geofence around (0, 0), with the most vertices
+fn octagon() -> Geofence {
+	Geofence::Polygon(vec![
+		read_point(3, 0),
+		read_point(2, 2),
+		read_point(0, 3),
+		read_point(-2, 2),
+		read_point(-3, 0),
+		read_point(-2, -2),
+		read_point(0, -3),
+		read_point(2, -2),
+	])
+}
+
+fn products() -> Vec<ProductId> {
+	(0..SHIPMENT_MAX_PRODUCTS as u32).map(|i| i.encode()).collect()
+}
+
+// A paid shipment in transit, with a temperature rule and a deadline
+fn store_in_transit<T: Trait>(id: &[u8], owner: T::AccountId) {
+	let shipment = Module::<T>::new_shipment()
+		.identified_by(id.to_vec())
+		.owned_by(owner.clone())
+		.registered_on(moment::<T>(0))
+		.with_products(products())
+		.build()
+		.pickup();
+	<Shipments<T>>::insert(id, shipment);
+	ThresholdRules::insert(id, ReadingType::Temperature, ReadingThreshold {
+		min: None,
+		max: Some(Decimal::from_num(8)),
+	});
+	<DeliveryDeadlines<T>>::insert(id, moment::<T>(1_000));
+	<Payments<T>>::insert(id, Payment {
+		payer: owner.clone(),
+		carrier: owner,
+		amount: 100u32.into(),
+	});
+}
+
+// A container in transit, with an octagonal geofence around (0, 0), a first event and
+// `children` shipments loaded in it
+fn in_transit_container<T: Trait>(owner: T::AccountId, children: u32) {
+	store_in_transit::<T>(SHIPMENT_ID, owner.clone());
+	Geofences::insert(SHIPMENT_ID, octagon());
+
+	let pickup = ShippingEvent {
+		id: b"benchmark-pickup".to_vec(),
+		event_type: ShippingEventType::ShipmentPickup,
+		shipment_id: SHIPMENT_ID.to_vec(),
+		location: None,
+		readings: vec![],
+		timestamp: moment::<T>(0),
+	};
+	EventCount::put(1);
+	EventIndices::insert(&pickup.id, 1);
+	<AllEvents<T>>::insert(1, pickup);
+	EventsOfShipment::insert(SHIPMENT_ID, 0, 1);
+	EventCountOfShipment::insert(SHIPMENT_ID, 1);
+
+	// The children were picked up with the container
+	let children = (0..children).map(child_id).collect::<Vec<_>>();
+	for child in children.iter() {
+		store_in_transit::<T>(child, owner.clone());
+		EventsOfShipment::insert(child, 0, 1);
+		EventCountOfShipment::insert(child, 1);
+		ParentOf::insert(child, SHIPMENT_ID);
+	}
+	ChildrenOf::insert(SHIPMENT_ID, children);
+}
+
+fn child_id(i: u32) -> ShipmentId {
+	[b"benchmark-child-".to_vec(), i.encode()].concat()
+}
+
+// A delivery awaiting the consignee's acknowledgment, carrying the most products, paid and
+// with a deadline
+fn awaiting_acknowledgment_shipment<T: Trait>(owner: T::AccountId, consignee: T::AccountId) {
+	let shipment = Module::<T>::new_shipment()
+		.identified_by(SHIPMENT_ID.to_vec())
+		.owned_by(owner.clone())
+		.registered_on(moment::<T>(0))
+		.with_products(products())
+		.consigned_to(Some(consignee.clone()))
+		.build()
+		.update_status(ShipmentStatus::AwaitingAcknowledgment);
+	<Shipments<T>>::insert(SHIPMENT_ID, shipment);
+	<PendingAcknowledgments<T>>::insert(SHIPMENT_ID, (moment::<T>(0), T::AcknowledgmentWindow::get()));
+	<DeliveryDeadlines<T>>::insert(SHIPMENT_ID, moment::<T>(1_000));
+	<Payments<T>>::insert(SHIPMENT_ID, Payment {
+		payer: owner,
+		carrier: consignee,
+		amount: 100u32.into(),
+	});
+}
+
+// Readings of a registered device, each above the shipment's temperature rule
+fn signed_readings<T: Trait>(owner: T::AccountId, count: u32) -> Vec<Reading<T::Moment>> {
+	let public_key = sp_io::crypto::sr25519_generate(DEVICE_KEY_TYPE, None);
+	Devices::<T>::insert(DEVICE_ID, Device {
+		id: DEVICE_ID.to_vec(),
+		public_key,
+		owner,
+		revoked: false,
+	});
+
+	(0..count)
+		.map(|_| {
+			let mut reading = Reading {
+				device_id: DEVICE_ID.to_vec(),
+				reading_type: ReadingType::Temperature,
+				timestamp: moment::<T>(0),
+				value: Decimal::from_num(12),
+				signature: DeviceSignature::default(),
+			};
+			reading.signature = sp_io::crypto::sr25519_sign(
+				DEVICE_KEY_TYPE,
+				&public_key,
+				&reading.signing_payload(SHIPMENT_ID),
+			)
+			.expect("key was generated above; qed");
+			reading
+		})
+		.collect()
+}
+
+benchmarks! {
+	_ { }
+
+	register_shipment {
+		let caller: T::AccountId = whitelisted_caller();
+		Members::<T>::insert(&caller, vec![Role::Shipper]);
+		// The shipment's deadline joins a bucket of `q` queued deadlines
+		let q in 0 .. MAX_QUEUED_DEADLINES;
+		let deadline = moment::<T>(1_000);
+		let bucket = deadline / T::DeadlineBucketSize::get();
+		for i in 0..q {
+			<DeadlineBuckets<T>>::insert(bucket, i, i.encode());
+		}
+		<DeadlineCountOfBucket<T>>::insert(bucket, q);
+		QueuedDeadlines::put(q);
+	}: _(RawOrigin::Signed(caller.clone()), SHIPMENT_ID.to_vec(), caller.clone(), vec![], Some(deadline), None, None)
+	verify {
+		assert_eq!(Module::<T>::shipment_count_of_org(&caller), 1);
+		assert_eq!(Module::<T>::deadline_count_of_bucket(bucket), q + 1);
+	}
+
+	record_event {
+		let r in 0 .. MAX_READINGS;
+		let c in 0 .. CONTAINER_MAX_SHIPMENTS as u32;
+		let caller: T::AccountId = whitelisted_caller();
+		Members::<T>::insert(&caller, vec![Role::Carrier]);
+		in_transit_container::<T>(caller.clone(), c);
+		let event = ShippingEvent {
+			id: b"benchmark-delivery".to_vec(),
+			event_type: ShippingEventType::ShipmentDelivery,
+			shipment_id: SHIPMENT_ID.to_vec(),
+			location: Some(read_point(0, 0)),
+			readings: signed_readings::<T>(caller.clone(), r),
+			timestamp: moment::<T>(0),
+		};
+	}: _(RawOrigin::Signed(caller), event)
+	verify {
+		let shipment = Module::<T>::shipment_by_id(SHIPMENT_ID).expect("shipment was stored above; qed");
+		assert_eq!(shipment.status, ShipmentStatus::Delivered);
+		assert_eq!(shipment.excursions, r);
+		for i in 0..c {
+			let child = Module::<T>::shipment_by_id(child_id(i)).expect("child was stored above; qed");
+			assert_eq!(child.status, ShipmentStatus::Delivered);
+		}
+	}
+
+	confirm_delivery {
+		let owner: T::AccountId = account("owner", 0, 0);
+		let consignee: T::AccountId = whitelisted_caller();
+		awaiting_acknowledgment_shipment::<T>(owner, consignee.clone());
+	}: _(RawOrigin::Signed(consignee), SHIPMENT_ID.to_vec())
+	verify {
+		let shipment = Module::<T>::shipment_by_id(SHIPMENT_ID).expect("shipment was stored above; qed");
+		assert_eq!(shipment.status, ShipmentStatus::Delivered);
+		assert!(Module::<T>::payment(SHIPMENT_ID).is_none());
+	}
+
+	dispute_delivery {
+		let owner: T::AccountId = account("owner", 0, 0);
+		let consignee: T::AccountId = whitelisted_caller();
+		awaiting_acknowledgment_shipment::<T>(owner, consignee.clone());
+	}: _(RawOrigin::Signed(consignee), SHIPMENT_ID.to_vec())
+	verify {
+		let shipment = Module::<T>::shipment_by_id(SHIPMENT_ID).expect("shipment was stored above; qed");
+		assert_eq!(shipment.status, ShipmentStatus::Held);
+	}
+
+	hand_over_custody {
+		let caller: T::AccountId = whitelisted_caller();
+		let recipient: T::AccountId = account("recipient", 0, 0);
+		Members::<T>::insert(&caller, vec![Role::Carrier]);
+		store_in_transit::<T>(SHIPMENT_ID, caller.clone());
+	}: _(RawOrigin::Signed(caller.clone()), SHIPMENT_ID.to_vec(), recipient.clone())
+	verify {
+		assert_eq!(Module::<T>::pending_handoff(SHIPMENT_ID), Some((caller, recipient)));
+	}
+
+	register_device {
+		let caller: T::AccountId = whitelisted_caller();
+		Members::<T>::insert(&caller, vec![Role::SensorGateway]);
+	}: _(RawOrigin::Signed(caller.clone()), DEVICE_ID.to_vec(), DevicePublicKey::default(), caller.clone())
+	verify {
+		assert!(Module::<T>::device_by_id(DEVICE_ID).is_some());
+	}
+
+	revoke_device {
+		let caller: T::AccountId = whitelisted_caller();
+		Members::<T>::insert(&caller, vec![Role::SensorGateway]);
+		Devices::<T>::insert(DEVICE_ID, Device {
+			id: DEVICE_ID.to_vec(),
+			public_key: DevicePublicKey::default(),
+			owner: caller.clone(),
+			revoked: false,
+		});
+	}: _(RawOrigin::Signed(caller), DEVICE_ID.to_vec())
+	verify {
+		assert_eq!(Module::<T>::device_by_id(DEVICE_ID).map(|device| device.revoked), Some(true));
+	}
+
+	aggregate_shipments {
+		let c in 1 .. CONTAINER_MAX_SHIPMENTS as u32;
+		let caller: T::AccountId = whitelisted_caller();
+		Members::<T>::insert(&caller, vec![Role::Carrier]);
+		store_in_transit::<T>(SHIPMENT_ID, caller.clone());
+		let children = (0..c).map(child_id).collect::<Vec<_>>();
+		for child in children.iter() {
+			store_in_transit::<T>(child, caller.clone());
+		}
+	}: _(RawOrigin::Signed(caller), SHIPMENT_ID.to_vec(), children)
+	verify {
+		assert_eq!(Module::<T>::children_of(SHIPMENT_ID).len() as u32, c);
+	}
+
+	set_geofence {
+		let caller: T::AccountId = whitelisted_caller();
+		Members::<T>::insert(&caller, vec![Role::Shipper]);
+		store_in_transit::<T>(SHIPMENT_ID, caller.clone());
+	}: _(RawOrigin::Signed(caller), SHIPMENT_ID.to_vec(), octagon())
+	verify {
+		assert!(Module::<T>::geofence(SHIPMENT_ID).is_some());
+	}
+
+	set_threshold_rule {
+		let caller: T::AccountId = whitelisted_caller();
+		Members::<T>::insert(&caller, vec![Role::Shipper]);
+		store_in_transit::<T>(SHIPMENT_ID, caller.clone());
+		let rule = ReadingThreshold {
+			min: Some(Decimal::from_num(2)),
+			max: Some(Decimal::from_num(8)),
+		};
+	}: _(RawOrigin::Signed(caller), SHIPMENT_ID.to_vec(), ReadingType::Humidity, rule.clone())
+	verify {
+		assert_eq!(Module::<T>::threshold_rule(SHIPMENT_ID, ReadingType::Humidity), Some(rule));
+	}
+
+	remove_threshold_rule {
+		let caller: T::AccountId = whitelisted_caller();
+		Members::<T>::insert(&caller, vec![Role::Shipper]);
+		store_in_transit::<T>(SHIPMENT_ID, caller.clone());
+	}: _(RawOrigin::Signed(caller), SHIPMENT_ID.to_vec(), ReadingType::Temperature)
+	verify {
+		assert!(Module::<T>::threshold_rule(SHIPMENT_ID, ReadingType::Temperature).is_none());
+	}
+
+	grant_role {
+		// The last member that can join
+		let member: T::AccountId = account("member", 0, 0);
+		MemberCount::put(MAX_MEMBERS - 1);
+	}: _(T::AdminOrigin::successful_origin(), member.clone(), Role::Carrier)
+	verify {
+		assert_eq!(Module::<T>::roles_of(&member), vec![Role::Carrier]);
+	}
+
+	revoke_role {
+		// The member's last role
+		let member: T::AccountId = account("member", 0, 0);
+		Members::<T>::insert(&member, vec![Role::Carrier]);
+		MemberCount::put(1);
+	}: _(T::AdminOrigin::successful_origin(), member.clone(), Role::Carrier)
+	verify {
+		assert!(!Members::<T>::contains_key(&member));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::tests::{ExtBuilder, TestRuntime};
+	use frame_support::assert_ok;
+	use sp_core::{testing::KeyStore, traits::KeystoreExt};
+
+	#[test]
+	fn benchmarks_work() {
+		let mut ext = ExtBuilder::build();
+		ext.register_extension(KeystoreExt(KeyStore::new()));
+		ext.execute_with(|| {
+			assert_ok!(test_benchmark_register_shipment::<TestRuntime>());
+			assert_ok!(test_benchmark_record_event::<TestRuntime>());
+			assert_ok!(test_benchmark_confirm_delivery::<TestRuntime>());
+			assert_ok!(test_benchmark_dispute_delivery::<TestRuntime>());
+			assert_ok!(test_benchmark_hand_over_custody::<TestRuntime>());
+			assert_ok!(test_benchmark_register_device::<TestRuntime>());
+			assert_ok!(test_benchmark_revoke_device::<TestRuntime>());
+			assert_ok!(test_benchmark_aggregate_shipments::<TestRuntime>());
+			assert_ok!(test_benchmark_set_geofence::<TestRuntime>());
+			assert_ok!(test_benchmark_set_threshold_rule::<TestRuntime>());
+			assert_ok!(test_benchmark_remove_threshold_rule::<TestRuntime>());
+			assert_ok!(test_benchmark_grant_role::<TestRuntime>());
+			assert_ok!(test_benchmark_revoke_role::<TestRuntime>());
+		});
+	}
+}
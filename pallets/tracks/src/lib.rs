@@ -1,42 +1,349 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-use account_set::AccountSet;
+use account_set::{AccountSet, Escrow, ProductRegistry, Relation, RelationSet};
 use frame_support::storage::IterableStorageMap;
 use frame_support::{
-	decl_error, decl_event, decl_module, decl_storage, dispatch, dispatch::DispatchResult, ensure,
+	codec::{Decode, Encode},
+	decl_error, decl_event, decl_module, decl_storage, dispatch, dispatch::{DispatchError, DispatchResult}, ensure,
+	sp_runtime::RuntimeDebug,
+	traits::{EnsureOrigin, Get},
+	weights::Weight,
+	Parameter,
 };
 use frame_system::{self as system, ensure_signed};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_runtime::traits::{AtLeast32BitUnsigned, Hash, Member, One, Saturating, Verify, Zero};
 use sp_std::collections::btree_set::BTreeSet;
 use sp_std::prelude::*;
 
 use bluefn_util::catalog::ProductId;
+use bluefn_util::geofence::Geofence;
+use bluefn_util::gs1::IdentifierValidator;
+use bluefn_util::merkle::merkle_root;
 use bluefn_util::shipment::*;
 
+mod batch;
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+pub mod migration;
+mod weights;
+
+pub use weights::WeightInfo;
+
+use batch::EventBatch;
+
 #[cfg(test)]
 mod tests;
 
 /// A maximum number of members. When membership reaches this number, no new members may join.
 pub const MAX_MEMBERS: u32 = 16;
 
+/// Releases of the pallet's storage layout, used to run migrations once.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum Releases {
+	/// Unsigned `ReadPoint` coordinates, no excursion counter nor custodian on shipments
+	V1,
+	/// Signed `ReadPoint` coordinates, device signatures on readings
+	V2,
+	/// Paged shipment and event indexes
+	V3,
+	/// Optional consignee on shipments
+	V4,
+	/// Role-scoped membership
+	V5,
+	/// Index of the shipments carrying a product
+	V6,
+	/// Delivery deadlines grouped in time buckets
+	V7,
+	/// Index of each organization's shipments by ID
+	V8,
+	/// Signed reading values and threshold bounds
+	V9,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1
+	}
+}
+
+/// Roles a member of the pallet can be granted, scoping the calls it may make
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum Role {
+	/// Registers shipments and sets their rules
+	Shipper,
+	/// Moves shipments, recording their pickup, delivery and incidents
+	Carrier,
+	/// Operates sensor devices, recording their readings
+	SensorGateway,
+	/// Inspects shipments, possibly holding them or reporting damage
+	Auditor,
+}
+
+impl Role {
+	/// Returns the roles allowed to record shipping events of a type
+	pub fn recording(event_type: &ShippingEventType) -> &'static [Role] {
+		match event_type {
+			ShippingEventType::SensorReading => &[Role::SensorGateway, Role::Carrier],
+			ShippingEventType::ShipmentCancellation | ShippingEventType::OwnershipTransfer => &[Role::Shipper],
+			ShippingEventType::ShipmentHold | ShippingEventType::ShipmentDamage => &[Role::Carrier, Role::Auditor],
+			_ => &[Role::Carrier],
+		}
+	}
+}
+
 pub trait Trait: system::Trait + timestamp::Trait {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The origin that grants and revokes the roles of members
+	type AdminOrigin: EnsureOrigin<Self::Origin>;
+
+	/// A type that tells whether an account is a delegate of an organization.
+	/// Notice the loose coupling: any pallet that implements `RelationSet` works here.
+	type DelegationSource: RelationSet<OrgId = Self::AccountId>;
+
+	/// A type that tells whether a product is registered, so that shipments only carry known products,
+	/// and who owns it, so that only its owner recalls it.
+	/// Any pallet that implements `ProductRegistry` works here.
+	type ProductRegistry: ProductRegistry<ProductId = ProductId, AccountId = Self::AccountId>;
+
+	/// The balance of shipment payments
+	type Balance: Parameter + Member + AtLeast32BitUnsigned + Default + Copy;
+
+	/// A type that holds shipment payments until delivery, e.g. the `basic-token` pallet.
+	/// Any pallet that implements `Escrow` works here.
+	type Escrow: Escrow<AccountId = Self::AccountId, Balance = Self::Balance>;
+
+	/// Whether deliveries recorded outside of a shipment's geofence are rejected,
+	/// or only flagged with a `DeliveryOutsideGeofence` event.
+	type RejectDeliveriesOutsideGeofence: Get<bool>;
+
+	/// How far ahead of the block's timestamp shipping events may be dated, to tolerate
+	/// clocks of devices and gateways running slightly fast
+	type MaxClockSkew: Get<Self::Moment>;
+
+	/// How long the events of a delivered shipment are kept, before being pruned
+	type RetentionPeriod: Get<Self::Moment>;
+
+	/// Maximum number of events pruned in a block
+	type MaxPrunedEventsPerBlock: Get<u32>;
+
+	/// Maximum length of shipment and shipping event identifiers
+	type MaxIdentifierLength: Get<u32>;
+
+	/// Checks the structure of shipment IDs, e.g. `bluefn_util::gs1::Sscc` for SSCCs
+	type ShipmentIdValidator: IdentifierValidator;
+
+	/// Maximum number of shipments an organization can register or receive, including those
+	/// it transferred since
+	type MaxShipmentsPerOrganization: Get<u32>;
+
+	/// Maximum number of shipments checked in a block by product recalls, to hold them
+	type MaxRecalledShipmentsPerBlock: Get<u32>;
+
+	/// Maximum number of events recorded for a shipment, including those of its container
+	type MaxEventsPerShipment: Get<u32>;
+
+	/// Maximum number of events recorded at once by `record_events`
+	type MaxEventsPerBatch: Get<u32>;
+
+	/// Maximum number of delivery deadlines checked in a block
+	type MaxOverdueChecksPerBlock: Get<u32>;
+
+	/// Length of the time buckets delivery deadlines are grouped in. A bucket is checked
+	/// once all of it elapsed, so deadlines are reported up to this late. Must not be zero.
+	type DeadlineBucketSize: Get<Self::Moment>;
+
+	/// How long the consignee of a shipment has to confirm or dispute its delivery,
+	/// before the delivery is considered confirmed
+	type AcknowledgmentWindow: Get<Self::Moment>;
+
+	/// Maximum number of expired acknowledgment windows processed in a block
+	type MaxExpiredAcknowledgmentsPerBlock: Get<u32>;
+
+	/// Weights of the pallet's calls
+	type WeightInfo: WeightInfo;
 }
 
 decl_storage! {
 	trait Store for Module<T: Trait> as VecMap {
-		//Currently we map to '()' because '()' is not encoded anymore as 0 bytes and the underlying storage
-		Members get(fn members): map hasher(blake2_128_concat) T::AccountId => ();
+		/// Roles of the members. An account without roles isn't a member.
+		pub Members get(fn roles_of): map hasher(blake2_128_concat) T::AccountId => Vec<Role>;
 		// The total number of members stored in the map.
 		// Because the map does not store its size internally, we must store it separately
 		MemberCount: u32;
 
 		pub Shipments get(fn shipment_by_id): map hasher(blake2_128_concat) ShipmentId => Option<Shipment<T::AccountId, T::Moment>>;
-        pub ShipmentsOfOrganization get(fn shipments_of_org): map hasher(blake2_128_concat) T::AccountId => Vec<ShipmentId>;
+        /// Shipments of an organization, by index. Paged so that registering a shipment
+        /// doesn't rewrite the whole list. Shipments transferred away leave a gap.
+        pub ShipmentsOfOrganization get(fn shipment_of_org_by_idx): double_map hasher(blake2_128_concat) T::AccountId, hasher(twox_64_concat) u32 => Option<ShipmentId>;
+        pub ShipmentCountOfOrganization get(fn shipment_count_of_org): map hasher(blake2_128_concat) T::AccountId => u32;
+        /// Index of a shipment among its organization's shipments
+        pub ShipmentIndexOfOrganization get(fn shipment_idx_of_org): double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) ShipmentId => Option<u32>;
+        /// Shipments that carried a product, by index. Closed shipments stay listed.
+        pub ShipmentsOfProduct get(fn shipment_of_product_by_idx): double_map hasher(blake2_128_concat) ProductId, hasher(twox_64_concat) u32 => Option<ShipmentId>;
+        pub ShipmentCountOfProduct get(fn shipment_count_of_product): map hasher(blake2_128_concat) ProductId => u32;
+        /// Recalled products, with the time of their recall. New shipments can't carry them.
+        pub RecalledProducts get(fn recalled_on): map hasher(blake2_128_concat) ProductId => Option<T::Moment>;
+        /// Recalled products whose shipments are still to hold. A FIFO queue, worked through
+        /// in `on_initialize`.
+        RecallQueue: map hasher(twox_64_concat) u64 => Option<ProductId>;
+        RecallQueueHead: u64;
+        RecallQueueTail: u64;
+        /// Index of the next shipment to hold, in the product at the head of the queue
+        RecallCursor: u32;
 
         pub EventCount get(fn event_count): u64;
         pub AllEvents get(fn event_by_idx): map hasher(blake2_128_concat) ShippingEventIndex => Option<ShippingEvent<T::Moment>>;
         pub EventIndices get(fn event_idx_from_id): map hasher(blake2_128_concat) ShippingEventId => Option<ShippingEventIndex>;
-        pub EventsOfShipment get(fn events_of_shipment): map hasher(blake2_128_concat) ShipmentId => Vec<ShippingEventIndex>;
+        /// Events of a shipment, by index. Pruned events leave a gap at the start.
+        pub EventsOfShipment get(fn event_of_shipment_by_idx): double_map hasher(blake2_128_concat) ShipmentId, hasher(twox_64_concat) u32 => Option<ShippingEventIndex>;
+        pub EventCountOfShipment get(fn event_count_of_shipment): map hasher(blake2_128_concat) ShipmentId => u32;
+
+        /// Storage layout release. New chains start on the latest release.
+        StorageVersion build(|_| Releases::V9): Releases;
+
+        pub Devices get(fn device_by_id): map hasher(blake2_128_concat) DeviceId => Option<Device<T::AccountId>>;
+
+        /// Custody handoffs offered by a shipment's custodian, as (from, to)
+        pub PendingHandoffs get(fn pending_handoff): map hasher(blake2_128_concat) ShipmentId => Option<(T::AccountId, T::AccountId)>;
+        /// Ownership transfers waiting for the new owner's acceptance, as (from, to, event)
+        pub PendingTransfers get(fn pending_transfer): map hasher(blake2_128_concat) ShipmentId => Option<(T::AccountId, T::AccountId, ShippingEvent<T::Moment>)>;
+        /// Custody history of a shipment, by index
+        pub CustodyHistory get(fn custody_record): double_map hasher(blake2_128_concat) ShipmentId, hasher(twox_64_concat) u32 => Option<CustodyRecord<T::AccountId, T::Moment>>;
+        pub CustodyCount get(fn custody_count): map hasher(blake2_128_concat) ShipmentId => u32;
+
+        /// Shipments loaded in a container, and the container a shipment is loaded in
+        pub ChildrenOf get(fn children_of): map hasher(blake2_128_concat) ShipmentId => Vec<ShipmentId>;
+        pub ParentOf get(fn parent_of): map hasher(blake2_128_concat) ShipmentId => Option<ShipmentId>;
+
+        /// Delivered shipments waiting for their events to be pruned, as (shipment, prune after).
+        /// A FIFO queue: shipments are delivered, hence can be pruned, in order.
+        PruningQueue: map hasher(twox_64_concat) u64 => Option<(ShipmentId, T::Moment)>;
+        PruningQueueHead: u64;
+        PruningQueueTail: u64;
+        /// Index of the next event to prune, in the shipment at the head of the queue
+        PruningCursor: u32;
+        /// Merkle root of a pruned shipment's events, see `bluefn_util::merkle`
+        pub ArchivedRoots get(fn archived_root): map hasher(blake2_128_concat) ShipmentId => Option<T::Hash>;
+
+        pub Geofences get(fn geofence): map hasher(blake2_128_concat) ShipmentId => Option<Geofence>;
+
+        pub ThresholdRules get(fn threshold_rule): double_map hasher(blake2_128_concat) ShipmentId, hasher(blake2_128_concat) ReadingType => Option<ReadingThreshold>;
+        /// Summary of the readings of each type recorded for a shipment, kept when its events are pruned
+        pub ReadingStatistics get(fn reading_stats): double_map hasher(blake2_128_concat) ShipmentId, hasher(blake2_128_concat) ReadingType => Option<ReadingStats<T::Moment>>;
+
+        /// Expected delivery of a shipment, if given at registration
+        pub DeliveryDeadlines get(fn delivery_deadline): map hasher(blake2_128_concat) ShipmentId => Option<T::Moment>;
+        /// Shipments whose deadline isn't checked yet, by bucket and index. A bucket holds the
+        /// deadlines from `bucket * DeadlineBucketSize` until the next bucket.
+        pub DeadlineBuckets get(fn deadline_by_idx): double_map hasher(twox_64_concat) T::Moment, hasher(twox_64_concat) u32 => Option<ShipmentId>;
+        pub DeadlineCountOfBucket get(fn deadline_count_of_bucket): map hasher(twox_64_concat) T::Moment => u32;
+        /// Next deadline to check, as (bucket, index)
+        pub DeadlineCursor get(fn deadline_cursor): (T::Moment, u32);
+        /// Number of deadlines queued in buckets and not checked yet
+        pub QueuedDeadlines get(fn queued_deadlines): u32;
+        /// How long after its deadline a shipment was delivered, zero when on time
+        pub DeliveryLateness get(fn delivery_lateness): map hasher(blake2_128_concat) ShipmentId => Option<T::Moment>;
+
+        /// Payments held in escrow until a shipment is delivered, cancelled or overdue
+        pub Payments get(fn payment): map hasher(blake2_128_concat) ShipmentId => Option<Payment<T::AccountId, T::Balance>>;
+
+        /// Deliveries waiting for the consignee's acknowledgment, as (delivered, window expiry)
+        pub PendingAcknowledgments get(fn pending_acknowledgment): map hasher(blake2_128_concat) ShipmentId => Option<(T::Moment, T::Moment)>;
+        /// Acknowledgment windows, as (shipment, expiry). A FIFO queue: windows have the
+        /// same length, hence expire in order.
+        AcknowledgmentQueue: map hasher(twox_64_concat) u64 => Option<(ShipmentId, T::Moment)>;
+        AcknowledgmentQueueHead: u64;
+        AcknowledgmentQueueTail: u64;
+	}
+	add_extra_genesis {
+		/// Members and their roles
+		config(members): Vec<(T::AccountId, Vec<Role>)>;
+		/// Shipments, as (id, owner, status, products, registered). Shipments are registered
+		/// pending, and their events must bring them to the given status.
+		config(shipments): Vec<(ShipmentId, T::AccountId, ShipmentStatus, Vec<ProductId>, u64)>;
+		/// Sensor devices, signing the readings of the genesis events
+		config(devices): Vec<Device<T::AccountId>>;
+		/// Shipping events with the members recording them, in the order they were recorded.
+		/// They're checked as if recorded at their timestamp.
+		config(events): Vec<(T::AccountId, ShippingEvent<u64>)>;
+		build(|config: &GenesisConfig<T>| {
+			use sp_runtime::SaturatedConversion;
+			let moment = |ms: u64| -> T::Moment { ms.saturated_into() };
+
+			assert!(config.members.len() as u32 <= MAX_MEMBERS, "Too many genesis members");
+			for (who, roles) in config.members.iter() {
+				assert!(!roles.is_empty(), "Genesis member without roles");
+				assert!(!Members::<T>::contains_key(who), "Duplicate genesis member");
+				Members::<T>::insert(who, roles);
+			}
+			MemberCount::put(config.members.len() as u32);
+
+			for (id, owner, _, products, registered) in config.shipments.iter() {
+				assert!(
+					Module::<T>::validate_identifier(id).is_ok() && T::ShipmentIdValidator::is_valid(id),
+					"Invalid genesis shipment ID"
+				);
+				assert!(!<Shipments<T>>::contains_key(id), "Duplicate genesis shipment");
+				assert!(products.len() <= SHIPMENT_MAX_PRODUCTS, "Genesis shipment has too many products");
+				assert!(
+					Module::<T>::validate_org_capacity(owner).is_ok(),
+					"Genesis organization has too many shipments"
+				);
+
+				let shipment = Module::<T>::new_shipment()
+					.identified_by(id.clone())
+					.owned_by(owner.clone())
+					.registered_on(moment(*registered))
+					.with_products(products.clone())
+					.build();
+				<Shipments<T>>::insert(id, shipment);
+				Module::<T>::index_shipment_of_org(owner, id);
+				Module::<T>::index_products(id, products);
+			}
+
+			for device in config.devices.iter() {
+				assert!(Module::<T>::validate_device_id(&device.id).is_ok(), "Invalid genesis device ID");
+				assert!(!Devices::<T>::contains_key(&device.id), "Duplicate genesis device");
+				Devices::<T>::insert(&device.id, device);
+			}
+
+			// Events are replayed through the checks of `record_event`, so custody and ownership
+			// don't change at genesis
+			for (who, event) in config.events.iter() {
+				let timestamp = moment(event.timestamp);
+				let readings = event.readings.iter().map(|reading| Reading {
+					device_id: reading.device_id.clone(),
+					reading_type: reading.reading_type,
+					timestamp: moment(reading.timestamp),
+					value: reading.value,
+					signature: reading.signature.clone(),
+				}).collect::<Vec<_>>();
+				let mut batch = EventBatch::<T>::at(who.clone(), timestamp);
+				let result = Module::<T>::validate_recordable(&event.event_type).and_then(|_| batch.record(ShippingEvent {
+					id: event.id.clone(),
+					event_type: event.event_type.clone(),
+					shipment_id: event.shipment_id.clone(),
+					location: event.location.clone(),
+					readings,
+					timestamp,
+				}));
+				if let Err(err) = result {
+					panic!("Invalid genesis event: {}", err.as_str());
+				}
+				batch.commit();
+			}
+
+			for (id, _, status, _, _) in config.shipments.iter() {
+				assert!(
+					<Shipments<T>>::get(id).map_or(false, |shipment| shipment.status == *status),
+					"Genesis shipment status doesn't match its events"
+				);
+			}
+		});
 	}
 }
 
@@ -44,35 +351,200 @@ decl_event!(
 	pub enum Event<T>
 	where
 		AccountId = <T as system::Trait>::AccountId,
+		Hash = <T as system::Trait>::Hash,
+		Moment = <T as timestamp::Trait>::Moment,
+		Balance = <T as Trait>::Balance,
 	{
-		/// Added a member
-		MemberAdded(AccountId),
-		/// Removed a member
-		MemberRemoved(AccountId),
+		/// A role was granted to an account
+		RoleGranted(AccountId, Role),
+		/// A role was revoked from an account
+		RoleRevoked(AccountId, Role),
 
 		ShipmentRegistered(AccountId, ShipmentId, AccountId),
+		/// A delegate acted on behalf of an organization. Parameters are (organization, delegate, shipment)
+		DelegationUsed(AccountId, AccountId, ShipmentId),
         ShipmentStatusUpdated(ShipmentId, ShipmentStatus),
         ShippingEventRecorded(AccountId, ShippingEventId, ShipmentId, ShippingEventType),
+        /// A shipping event of a batch was refused, the others were recorded. Parameters are (sender, event, error)
+        ShippingEventRejected(AccountId, ShippingEventId, DispatchError),
+        /// A batch of shipping events was recorded. Parameters are (sender, recorded, rejected)
+        ShippingEventBatchRecorded(AccountId, u32, u32),
+        /// A custodian offered a shipment to another party. Parameters are (from, to, shipment)
+        CustodyHandoffInitiated(AccountId, AccountId, ShipmentId),
+        /// Custody of a shipment changed hands. Parameters are (from, to, shipment)
+        CustodyTransferred(AccountId, AccountId, ShipmentId),
+        /// An owner offered a shipment to a new owner, who must accept it. Parameters are (from, to, shipment)
+        ShipmentTransferOffered(AccountId, AccountId, ShipmentId),
+        /// Ownership of a shipment changed. Parameters are (from, to, shipment)
+        ShipmentTransferred(AccountId, AccountId, ShipmentId),
+        /// A device was registered. Parameters are (sender, device, owner)
+        DeviceRegistered(AccountId, DeviceId, AccountId),
+        /// A device was revoked, its readings are refused from now on
+        DeviceRevoked(AccountId, DeviceId),
+        /// Shipments were loaded in a container. Parameters are (sender, container, shipments)
+        ShipmentsAggregated(AccountId, ShipmentId, Vec<ShipmentId>),
+        /// Shipments were unloaded from a container. Parameters are (container, shipments)
+        ShipmentsDisaggregated(ShipmentId, Vec<ShipmentId>),
+        /// The events of a shipment are being pruned, and archived under a Merkle root.
+        /// Parameters are (shipment, root, number of events)
+        ShipmentEventsArchived(ShipmentId, Hash, u32),
+        /// A destination geofence was set on a shipment. Parameters are (sender, shipment)
+        GeofenceSet(AccountId, ShipmentId),
+        /// A delivery was recorded without a location or outside the shipment's geofence.
+        /// Parameters are (shipment, event)
+        DeliveryOutsideGeofence(ShipmentId, ShippingEventId),
+        /// A threshold rule was set on a shipment. Parameters are (sender, shipment, reading type, rule)
+        ThresholdRuleSet(AccountId, ShipmentId, ReadingType, ReadingThreshold),
+        /// A threshold rule was removed from a shipment
+        ThresholdRuleRemoved(AccountId, ShipmentId, ReadingType),
+        /// A reading fell outside the shipment's threshold rule. Parameters are (shipment, event, reading type, value)
+        ThresholdExcursion(ShipmentId, ShippingEventId, ReadingType, Decimal),
+        /// A shipment wasn't delivered by its deadline. Parameters are (shipment, deadline)
+        ShipmentOverdue(ShipmentId, Moment),
+        /// A payment was locked in escrow for a shipment. Parameters are (shipment, payer, carrier, amount)
+        PaymentLocked(ShipmentId, AccountId, AccountId, Balance),
+        /// The payment of a delivered shipment was paid to the carrier. Parameters are (shipment, carrier, amount)
+        PaymentReleased(ShipmentId, AccountId, Balance),
+        /// The payment of a cancelled or overdue shipment was returned. Parameters are (shipment, payer, amount)
+        PaymentRefunded(ShipmentId, AccountId, Balance),
+        /// The consignee confirmed the delivery of a shipment. Parameters are (consignee, shipment)
+        DeliveryConfirmed(AccountId, ShipmentId),
+        /// The consignee disputed the delivery of a shipment, which is held. Parameters are (consignee, shipment)
+        DeliveryDisputed(AccountId, ShipmentId),
+        /// The consignee didn't acknowledge a delivery in time, which is considered confirmed
+        DeliveryAcknowledgmentExpired(ShipmentId),
+        /// A product was recalled by its owner. Parameters are (owner, product)
+        ProductRecalled(AccountId, ProductId),
+        /// A shipment carrying a recalled product was held. Parameters are (product, shipment)
+        ShipmentRecalled(ProductId, ShipmentId),
 	}
 );
 
 decl_error! {
 	pub enum Error for Module<T: Trait> {
-		/// Cannot join as a member because you are already a member
-		AlreadyMember,
-		/// Cannot give up membership because you are not currently a member
-		NotMember,
+		/// The account already has this role
+		AlreadyHasRole,
+		/// The account doesn't have this role
+		MissingRole,
 		/// Cannot add another member because the limit is already reached
 		MembershipLimitReached,
+		/// The sender doesn't have a role the call, or the shipping event, requires
+		SenderLacksRole,
+
+		/// The sender is neither the owner nor a delegate of the owning organization
+		SenderIsNotOwnerOrDelegate,
 
 		InvalidOrMissingIdentifier,
+        /// The shipment ID is malformed, e.g. its check digit doesn't match
+        InvalidShipmentId,
         ShipmentAlreadyExists,
         ShipmentHasBeenDelivered,
         ShipmentIsInTransit,
         ShipmentIsUnknown,
         ShipmentHasTooManyProducts,
+        /// A product isn't registered, or was deregistered
+        ProductIsUnknown,
+        /// A product was recalled, shipments can't carry it anymore
+        ProductIsRecalled,
+        /// The product was already recalled
+        ProductAlreadyRecalled,
+        /// Only the product's owner can recall it
+        SenderIsNotProductOwner,
+        /// The shipment, or one loaded in it, carries a recalled product. It can only be held,
+        /// cancelled or returned.
+        ShipmentIsRecalled,
+        /// The expected delivery must be later than now
+        DeliveryDeadlineInPast,
+        /// The sender can't afford the shipment's payment
+        InsufficientFundsForPayment,
+        /// Only the shipment's consignee can acknowledge its delivery
+        SenderIsNotConsignee,
+        /// The shipment's delivery wasn't recorded, or was already acknowledged
+        ShipmentIsNotAwaitingAcknowledgment,
+        /// The acknowledgment window of the delivery has expired
+        AcknowledgmentWindowExpired,
+        /// The shipping event isn't allowed in the shipment's current status
+        ShipmentStatusTransitionNotAllowed,
         ShippingEventAlreadyExists,
         ShippingEventMaxExceeded,
+        /// A batch needs at least one shipping event
+        EmptyShippingEventBatch,
+        /// A batch can't hold more than `MaxEventsPerBatch` shipping events
+        ShippingEventBatchTooLarge,
+        /// The shipping event is dated before the shipment was registered
+        ShippingEventBeforeRegistration,
+        /// The shipping event is dated later than now, beyond `MaxClockSkew`
+        ShippingEventInFuture,
+        /// The shipping event is dated before the shipment's latest event
+        ShippingEventOutOfOrder,
+        /// A reading is dated before the shipment was registered
+        ReadingBeforeRegistration,
+        /// A reading is dated after the shipping event carrying it
+        ReadingAfterShippingEvent,
+        /// Custody handoffs are recorded through `accept_custody`
+        CustodyHandoffMustBeAccepted,
+        /// Only `CustodyHandoff` shipping events can accept custody
+        ShippingEventIsNotCustodyHandoff,
+        /// Only the shipment's current custodian can hand it over
+        SenderIsNotCustodian,
+        /// The custodian can't hand a shipment over to itself
+        CustodyHandoffToSelf,
+        /// The shipment's custodian hasn't offered it to anyone
+        NoPendingCustodyHandoff,
+        /// The shipment was offered to another party
+        SenderIsNotCustodyRecipient,
+        /// Ownership transfers are recorded through `transfer_shipment`
+        ShipmentTransferRequired,
+        /// Only `OwnershipTransfer` shipping events, without readings, can transfer a shipment
+        ShippingEventIsNotOwnershipTransfer,
+        /// Only the shipment's owner can transfer it
+        SenderIsNotOwner,
+        /// The owner can't transfer a shipment to itself
+        ShipmentTransferToSelf,
+        /// The shipment's owner hasn't offered it to anyone
+        NoPendingShipmentTransfer,
+        /// The shipment was offered to another party
+        SenderIsNotTransferRecipient,
+        /// A threshold rule needs at least one bound, and its minimum can't exceed its maximum
+        InvalidThresholdRule,
+        /// No threshold rule is set for this shipment and reading type
+        ThresholdRuleIsUnknown,
+        /// A device with this ID is already registered
+        DeviceAlreadyRegistered,
+        /// The reading's device was never registered
+        DeviceIsUnregistered,
+        /// The reading's device has been revoked
+        DeviceIsRevoked,
+        /// The reading's signature doesn't match the device's public key
+        InvalidReadingSignature,
+        /// The shipment is closed (delivered, cancelled, returned or lost)
+        ShipmentIsClosed,
+        /// An aggregation needs at least one shipment
+        NoShipmentsToAggregate,
+        /// A shipment is listed more than once
+        DuplicateShipmentInAggregation,
+        /// A container can't hold more than `CONTAINER_MAX_SHIPMENTS` shipments
+        ContainerHasTooManyShipments,
+        /// Containers can't be nested
+        ShipmentIsContainer,
+        /// The shipment is already loaded in a container
+        ShipmentIsAlreadyAggregated,
+        /// The shipping event isn't allowed in the status of a shipment loaded in the container
+        ChildShipmentTransitionNotAllowed,
+        /// A geofence needs valid points, a positive radius or between 3 and 8 vertices
+        InvalidGeofence,
+        /// The shipment has a geofence, so its delivery must have a location
+        DeliveryLocationMissing,
+        /// The delivery location is outside of the shipment's geofence
+        DeliveryOutsideGeofence,
+        /// The organization registered `MaxShipmentsPerOrganization` shipments already
+        OrganizationHasTooManyShipments,
+        /// The shipment, or one loaded in it, recorded `MaxEventsPerShipment` events already
+        ShipmentHasTooManyEvents,
+        /// Latitude of a read point must be within ±90 degrees
+        LatitudeOutOfRange,
+        /// Longitude of a read point must be within ±180 degrees
+        LongitudeOutOfRange,
 	}
 }
 
@@ -82,138 +554,564 @@ decl_module! {
 
 		type Error = Error<T>;
 
-		/// Adds a member to the membership set
-		#[weight = 10_000]
-		fn add_member(origin) -> DispatchResult {
-			let new_member = ensure_signed(origin)?;
+		fn on_runtime_upgrade() -> Weight {
+			migration::migrate_to_v2::<T>() + migration::migrate_to_v3::<T>() + migration::migrate_to_v4::<T>()
+				+ migration::migrate_to_v5::<T>() + migration::migrate_to_v6::<T>() + migration::migrate_to_v7::<T>()
+				+ migration::migrate_to_v8::<T>() + migration::migrate_to_v9::<T>()
+		}
 
-			let member_count = MemberCount::get();
-			ensure!(member_count < MAX_MEMBERS, Error::<T>::MembershipLimitReached);
+		fn on_initialize(_n: T::BlockNumber) -> Weight {
+			// Deadlines are checked in `on_finalize`, once the block's timestamp is set
+			let checks = T::MaxOverdueChecksPerBlock::get() as Weight;
+			let expiries = T::MaxExpiredAcknowledgmentsPerBlock::get() as Weight;
+			Self::prune_events(<timestamp::Module<T>>::now())
+				.saturating_add(Self::hold_recalled_shipments())
+				.saturating_add(T::DbWeight::get().reads_writes(
+					4 + 4 * checks + (4 + SHIPMENT_MAX_PRODUCTS as Weight) * expiries,
+					2 + 3 * checks + 9 * expiries,
+				))
+		}
 
-			// We don't want to add duplicate members, so we check whether the potential new
-			// member is already present in the list. Because the membership is stored as a hash
-			// map this check is constant time O(1)
-			ensure!(!Members::<T>::contains_key(&new_member), Error::<T>::AlreadyMember);
+		fn on_finalize(_n: T::BlockNumber) {
+			let now = <timestamp::Module<T>>::now();
+			Self::check_deadlines(now);
+			Self::expire_acknowledgments(now);
+		}
+
+		fn integrity_test() {
+			// Deadlines are bucketed by dividing by it
+			assert!(!T::DeadlineBucketSize::get().is_zero(), "DeadlineBucketSize must not be zero");
+		}
+
+		/// Grants a role to an account, which becomes a member if it wasn't one
+		#[weight = T::WeightInfo::grant_role()]
+		pub fn grant_role(origin, who: T::AccountId, role: Role) -> DispatchResult {
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			let mut roles = Members::<T>::get(&who);
+			ensure!(!roles.contains(&role), Error::<T>::AlreadyHasRole);
 
-			// Insert the new member and emit the event
-			Members::<T>::insert(&new_member, ());
-			MemberCount::put(member_count + 1); // overflow check not necessary because of maximum
-			Self::deposit_event(RawEvent::MemberAdded(new_member));
+			// Only new members count towards the limit
+			if roles.is_empty() {
+				let member_count = MemberCount::get();
+				ensure!(member_count < MAX_MEMBERS, Error::<T>::MembershipLimitReached);
+				MemberCount::put(member_count + 1); // overflow check not necessary because of maximum
+			}
+
+			roles.push(role);
+			Members::<T>::insert(&who, roles);
+			Self::deposit_event(RawEvent::RoleGranted(who, role));
 			Ok(())
 		}
 
-		/// Removes a member.
-		#[weight = 10_000]
-		fn remove_member(origin) -> DispatchResult {
-			let old_member = ensure_signed(origin)?;
+		/// Revokes a role from an account, which stops being a member without roles
+		#[weight = T::WeightInfo::revoke_role()]
+		pub fn revoke_role(origin, who: T::AccountId, role: Role) -> DispatchResult {
+			T::AdminOrigin::ensure_origin(origin)?;
 
-			ensure!(Members::<T>::contains_key(&old_member), Error::<T>::NotMember);
+			let mut roles = Members::<T>::get(&who);
+			ensure!(roles.contains(&role), Error::<T>::MissingRole);
 
-			Members::<T>::remove(&old_member);
-			MemberCount::mutate(|v| *v -= 1);
-			Self::deposit_event(RawEvent::MemberRemoved(old_member));
+			roles.retain(|granted| *granted != role);
+			if roles.is_empty() {
+				Members::<T>::remove(&who);
+				MemberCount::mutate(|v| *v -= 1);
+			} else {
+				Members::<T>::insert(&who, roles);
+			}
+			Self::deposit_event(RawEvent::RoleRevoked(who, role));
 			Ok(())
 		}
 
-		#[weight = 10_000]
-        pub fn register_shipment(origin, id: ShipmentId, owner: T::AccountId, products: Vec<ProductId>) -> dispatch::DispatchResult {
+		/// Registers a shipment for an organization. With an `expected_delivery`, the shipment
+		/// is reported by a `ShipmentOverdue` event if not delivered by then.
+		/// With a `payment` as (carrier, amount), the amount is taken from the sender and held
+		/// in escrow. It's paid to the carrier on delivery, and refunded if the shipment is
+		/// cancelled, returned, lost or overdue.
+		/// With a `consignee`, deliveries only complete once the consignee confirms them, or
+		/// doesn't dispute them within `AcknowledgmentWindow`.
+		#[weight = T::WeightInfo::register_shipment()
+			.saturating_add(T::DbWeight::get().reads_writes(3 * products.len() as Weight, 2 * products.len() as Weight))
+			.saturating_add(payment.as_ref().map_or(0, |_| T::DbWeight::get().reads_writes(2, 3)))]
+        pub fn register_shipment(
+            origin,
+            id: ShipmentId,
+            owner: T::AccountId,
+            products: Vec<ProductId>,
+            expected_delivery: Option<T::Moment>,
+            payment: Option<(T::AccountId, T::Balance)>,
+            consignee: Option<T::AccountId>,
+        ) -> dispatch::DispatchResult {
             let who = ensure_signed(origin)?;
+            Self::validate_role(&who, &[Role::Shipper])?;
+            let now = <timestamp::Module<T>>::now();
 
-            // TODO: assuming owner is a DID representing an organization,
-            //       validate tx sender is owner or delegate of organization.
+            // Validate the deadline, if any
+            if let Some(deadline) = expected_delivery {
+                ensure!(deadline > now, Error::<T>::DeliveryDeadlineInPast);
+            }
 
             // Validate format of shipment ID
             Self::validate_identifier(&id)?;
+            ensure!(T::ShipmentIdValidator::is_valid(&id), Error::<T>::InvalidShipmentId);
 
-            // Validate shipment products
+            // Validate shipment products are registered (1 DB read per product)
             Self::validate_shipment_products(&products)?;
 
+            // Validate tx sender is owner or delegate of organization (1 DB read)
+            let delegation = Self::validate_owner_or_delegate(&who, &owner)?;
+
             // Check shipment doesn't exist yet (1 DB read)
             Self::validate_new_shipment(&id)?;
 
+            // Check the organization can register another shipment (1 DB read)
+            Self::validate_org_capacity(&owner)?;
+
+            // Lock the payment, last as it moves funds (2 DB writes)
+            if let Some((_, amount)) = payment.as_ref() {
+                ensure!(T::Escrow::lock(&who, *amount), Error::<T>::InsufficientFundsForPayment);
+            }
+
             // Create a shipment instance
             let shipment = Self::new_shipment()
                 .identified_by(id.clone())
                 .owned_by(owner.clone())
-                .registered_on(<timestamp::Module<T>>::now())
-                .with_products(products)
+                .consigned_to(consignee)
+                .registered_on(now)
+                .with_products(products.clone())
                 .build();
             let status = shipment.status.clone();
 
             // Storage writes
             // --------------
-            // Add shipment (1 DB write)
+            // Add shipment (4 DB writes, 1 DB read)
             <Shipments<T>>::insert(&id, shipment);
-            <ShipmentsOfOrganization<T>>::append(&owner, &id);
+            Self::index_shipment_of_org(&owner, &id);
+            // Index the shipment by product (1 DB read and 2 DB writes per product)
+            Self::index_products(&id, &products);
+            // Queue the deadline (up to 5 DB writes, 2 DB reads)
+            if let Some(deadline) = expected_delivery {
+                <DeliveryDeadlines<T>>::insert(&id, deadline);
+                Self::queue_deadline(&id, deadline, now);
+            }
+            // Hold the payment (1 DB write)
+            if let Some((carrier, amount)) = payment {
+                <Payments<T>>::insert(&id, Payment { payer: who.clone(), carrier: carrier.clone(), amount });
+                Self::deposit_event(RawEvent::PaymentLocked(id.clone(), who.clone(), carrier, amount));
+            }
 
+            if let Some(relation) = delegation {
+                Self::deposit_event(RawEvent::DelegationUsed(relation.parent, relation.child, id.clone()));
+            }
             Self::deposit_event(RawEvent::ShipmentRegistered(who, id.clone(), owner));
             Self::deposit_event(RawEvent::ShipmentStatusUpdated(id, status));
 
             Ok(())
         }
 
-        #[weight = 10_000]
+        #[weight = Module::<T>::event_weight(&event)]
         pub fn record_event(origin, event: ShippingEvent<T::Moment>) -> dispatch::DispatchResult {
             let who = ensure_signed(origin)?;
+            Self::validate_recordable(&event.event_type)?;
 
-            // Validate extrinsic data (no storage access)
-            // -----------------------
-            // Validate format of event & shipment ID
-            Self::validate_identifier(&event.id)?;
-            Self::validate_identifier(&event.shipment_id)?;
+            let mut batch = EventBatch::<T>::new(who);
+            batch.record(event)?;
+            batch.commit();
+
+            Ok(())
+        }
+
+        /// Records several shipping events at once, e.g. readings uploaded by a gateway.
+        /// Events are recorded in order, so later events may depend on earlier ones.
+        /// With `all_or_nothing`, the first refused event fails the whole batch.
+        /// Otherwise refused events are reported by `ShippingEventRejected`, and the others recorded.
+        #[weight = events
+            .iter()
+            .map(Module::<T>::event_weight)
+            .fold(0, |total: Weight, weight| total.saturating_add(weight))
+            .max(T::WeightInfo::record_event(0, 0))]
+        pub fn record_events(origin, events: Vec<ShippingEvent<T::Moment>>, all_or_nothing: bool) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+
+            ensure!(!events.is_empty(), Error::<T>::EmptyShippingEventBatch);
+            ensure!(
+                events.len() <= T::MaxEventsPerBatch::get() as usize,
+                Error::<T>::ShippingEventBatchTooLarge
+            );
+
+            let mut batch = EventBatch::<T>::new(who.clone());
+            let mut rejected = Vec::new();
+            for event in events {
+                let event_id = event.id.clone();
+                let result = Self::validate_recordable(&event.event_type).and_then(|_| batch.record(event));
+                match result {
+                    Err(error) if all_or_nothing => return Err(error.into()),
+                    Err(error) => rejected.push((event_id, DispatchError::from(error))),
+                    Ok(_) => {}
+                }
+            }
+            let recorded = batch.len() as u32;
+            batch.commit();
+
+            let rejected_count = rejected.len() as u32;
+            for (event_id, error) in rejected {
+                Self::deposit_event(RawEvent::ShippingEventRejected(who.clone(), event_id, error));
+            }
+            Self::deposit_event(RawEvent::ShippingEventBatchRecorded(who, recorded, rejected_count));
+
+            Ok(())
+        }
+
+        /// Confirms the delivery of a shipment, as its consignee
+        #[weight = T::WeightInfo::confirm_delivery()]
+        pub fn confirm_delivery(origin, shipment_id: ShipmentId) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+
+            let shipment = <Shipments<T>>::get(&shipment_id).ok_or(Error::<T>::ShipmentIsUnknown)?;
+            ensure!(shipment.consignee.as_ref() == Some(&who), Error::<T>::SenderIsNotConsignee);
+            // Recalled goods aren't accepted, even before the recall holds the shipment
+            ensure!(!Self::carries_recalled_product(&shipment.products), Error::<T>::ShipmentIsRecalled);
+            let (delivered, _) = <PendingAcknowledgments<T>>::get(&shipment_id)
+                .ok_or(Error::<T>::ShipmentIsNotAwaitingAcknowledgment)?;
+
+            Self::finalize_delivery(&shipment_id, shipment, delivered);
+
+            Self::deposit_event(RawEvent::DeliveryConfirmed(who, shipment_id));
+
+            Ok(())
+        }
+
+        /// Disputes the delivery of a shipment, as its consignee, e.g. when goods are missing.
+        /// The shipment is held until it's picked up again, returned or cancelled.
+        #[weight = T::WeightInfo::dispute_delivery()]
+        pub fn dispute_delivery(origin, shipment_id: ShipmentId) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+
+            let shipment = <Shipments<T>>::get(&shipment_id).ok_or(Error::<T>::ShipmentIsUnknown)?;
+            ensure!(shipment.consignee.as_ref() == Some(&who), Error::<T>::SenderIsNotConsignee);
+            let (_, expiry) = <PendingAcknowledgments<T>>::get(&shipment_id)
+                .ok_or(Error::<T>::ShipmentIsNotAwaitingAcknowledgment)?;
+            ensure!(<timestamp::Module<T>>::now() <= expiry, Error::<T>::AcknowledgmentWindowExpired);
+
+            // Storage writes
+            // --------------
+            <PendingAcknowledgments<T>>::remove(&shipment_id);
+            <Shipments<T>>::insert(&shipment_id, shipment.update_status(ShipmentStatus::Held));
+
+            Self::deposit_event(RawEvent::DeliveryDisputed(who, shipment_id.clone()));
+            Self::deposit_event(RawEvent::ShipmentStatusUpdated(shipment_id, ShipmentStatus::Held));
+
+            Ok(())
+        }
+
+        /// Offers custody of a shipment to another party.
+        /// Only the current custodian can release the goods, and the handoff only
+        /// takes effect when the receiving party records it through `accept_custody`.
+        #[weight = T::WeightInfo::hand_over_custody()]
+        pub fn hand_over_custody(origin, shipment_id: ShipmentId, to: T::AccountId) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::validate_role(&who, &[Role::Shipper, Role::Carrier])?;
+
+            let shipment = <Shipments<T>>::get(&shipment_id).ok_or(Error::<T>::ShipmentIsUnknown)?;
+            ensure!(shipment.custodian == who, Error::<T>::SenderIsNotCustodian);
+            ensure!(who != to, Error::<T>::CustodyHandoffToSelf);
+            Self::validate_status_transition(&shipment.status, &ShippingEventType::CustodyHandoff)?;
+
+            <PendingHandoffs<T>>::insert(&shipment_id, (who.clone(), to.clone()));
+
+            Self::deposit_event(RawEvent::CustodyHandoffInitiated(who, to, shipment_id));
+
+            Ok(())
+        }
+
+        /// Accepts custody of a shipment offered through `hand_over_custody`,
+        /// recording the `CustodyHandoff` shipping event in the shipment's history.
+        #[weight = Module::<T>::event_weight(&event)]
+        pub fn accept_custody(origin, event: ShippingEvent<T::Moment>) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+
+            ensure!(
+                event.event_type == ShippingEventType::CustodyHandoff,
+                Error::<T>::ShippingEventIsNotCustodyHandoff
+            );
 
-            let event_id = event.id.clone();
-            let event_type = event.event_type.clone();
             let shipment_id = event.shipment_id.clone();
+            let timestamp = event.timestamp;
+            let (from, to) = <PendingHandoffs<T>>::get(&shipment_id).ok_or(Error::<T>::NoPendingCustodyHandoff)?;
+            ensure!(to == who, Error::<T>::SenderIsNotCustodyRecipient);
+
+            let mut batch = EventBatch::<T>::new(who);
+            let event_idx = batch.record(event)?;
+            batch.hand_over(&shipment_id, to.clone());
 
-            // Storage checks
+            // Storage writes
             // --------------
-            // Get event count (1 DB read)
-            let event_count = EventCount::get();
-            let event_idx = event_count.checked_add(1).ok_or(Error::<T>::ShippingEventMaxExceeded)?;
-            // Check event doesn't exist yet (1 DB read)
-            Self::validate_new_shipping_event(&event_id)?;
-
-            // Check shipment is known (1 DB read)
-            // Additionnally, we refuse some shipping events based on the shipment's status
-            let mut shipment = match <Shipments<T>>::get(&shipment_id)
-            {
-                Some(shipment) => {
-                    match shipment.status {
-                        ShipmentStatus::Delivered => Err(<Error<T>>::ShipmentHasBeenDelivered),
-                        ShipmentStatus::InTransit if event_type == ShippingEventType::ShipmentPickup => Err(<Error<T>>::ShipmentIsInTransit),
-                        _ => Ok(shipment)
-                    }
-                }
-                None => Err(<Error<T>>::ShipmentIsUnknown)
-            }?;
+            batch.commit();
+            <PendingHandoffs<T>>::remove(&shipment_id);
+            let custody_idx = CustodyCount::get(&shipment_id);
+            <CustodyHistory<T>>::insert(&shipment_id, custody_idx, CustodyRecord {
+                from: from.clone(),
+                to: to.clone(),
+                event_idx,
+                timestamp,
+            });
+            CustodyCount::insert(&shipment_id, custody_idx.saturating_add(1));
+
+            Self::deposit_event(RawEvent::CustodyTransferred(from, to, shipment_id));
+
+            Ok(())
+        }
+
+        /// Transfers ownership of a shipment to `new_owner`, e.g. when goods are sold in transit.
+        /// Only the owner can transfer a shipment, recording `event`, an `OwnershipTransfer`
+        /// shipping event, in its history. With `require_acceptance`, the transfer and its event
+        /// are held until the new owner accepts them through `accept_shipment_transfer`.
+        #[weight = T::WeightInfo::record_event(0, 0)
+            .saturating_add(T::DbWeight::get().reads_writes(2, 5))]
+        pub fn transfer_shipment(
+            origin,
+            event: ShippingEvent<T::Moment>,
+            new_owner: T::AccountId,
+            require_acceptance: bool,
+        ) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+
+            ensure!(
+                event.event_type == ShippingEventType::OwnershipTransfer && event.readings.is_empty(),
+                Error::<T>::ShippingEventIsNotOwnershipTransfer
+            );
+            let shipment_id = event.shipment_id.clone();
+            let shipment = <Shipments<T>>::get(&shipment_id).ok_or(Error::<T>::ShipmentIsUnknown)?;
+            ensure!(shipment.owner == who, Error::<T>::SenderIsNotOwner);
+            ensure!(who != new_owner, Error::<T>::ShipmentTransferToSelf);
+            Self::validate_org_capacity(&new_owner)?;
+
+            // The event is validated now, even when it's only recorded once the transfer is accepted
+            let mut batch = EventBatch::<T>::new(who.clone());
+            if require_acceptance {
+                batch.record(event.clone())?;
+                <PendingTransfers<T>>::insert(&shipment_id, (who.clone(), new_owner.clone(), event));
+                Self::deposit_event(RawEvent::ShipmentTransferOffered(who, new_owner, shipment_id));
+                return Ok(());
+            }
+            batch.record(event)?;
+            batch.transfer(&shipment_id, new_owner.clone());
+
+            // Storage writes
+            // --------------
+            batch.commit();
+            <PendingTransfers<T>>::remove(&shipment_id);
+            Self::move_shipment(&shipment_id, &who, &new_owner);
+
+            Self::deposit_event(RawEvent::ShipmentTransferred(who, new_owner, shipment_id));
+
+            Ok(())
+        }
+
+        /// Accepts a shipment offered through `transfer_shipment`, recording the offer's
+        /// `OwnershipTransfer` event as the new owner, who needs the Shipper role.
+        /// The event must still follow the shipment's latest event, otherwise the owner has
+        /// to offer the shipment again.
+        #[weight = T::WeightInfo::record_event(0, 0)
+            .saturating_add(T::DbWeight::get().reads_writes(2, 5))]
+        pub fn accept_shipment_transfer(origin, shipment_id: ShipmentId) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+
+            let (from, to, event) = <PendingTransfers<T>>::get(&shipment_id).ok_or(Error::<T>::NoPendingShipmentTransfer)?;
+            ensure!(to == who, Error::<T>::SenderIsNotTransferRecipient);
+            Self::validate_org_capacity(&to)?;
+
+            // The former owner may have left since the offer, the new owner records the event
+            let mut batch = EventBatch::<T>::new(to.clone());
+            batch.record(event)?;
+            batch.transfer(&shipment_id, to.clone());
+
+            // Storage writes
+            // --------------
+            batch.commit();
+            <PendingTransfers<T>>::remove(&shipment_id);
+            Self::move_shipment(&shipment_id, &from, &to);
+
+            Self::deposit_event(RawEvent::ShipmentTransferred(from, to, shipment_id));
+
+            Ok(())
+        }
+
+        /// Recalls a product, as its owner and a shipper. New shipments can't carry it, and open
+        /// shipments carrying it can't move on, see `validate_recall`. They are then held over the
+        /// next blocks, including deliveries awaiting acknowledgment.
+        /// A held shipment loaded in a container must be unloaded before the container moves on.
+        #[weight = T::DbWeight::get().reads_writes(4, 3)]
+        pub fn recall_product(origin, product_id: ProductId) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::validate_role(&who, &[Role::Shipper])?;
+
+            let owner = T::ProductRegistry::owner_of(&product_id).ok_or(Error::<T>::ProductIsUnknown)?;
+            ensure!(owner == who, Error::<T>::SenderIsNotProductOwner);
+            ensure!(!<RecalledProducts<T>>::contains_key(&product_id), Error::<T>::ProductAlreadyRecalled);
+
+            // Storage writes
+            // --------------
+            <RecalledProducts<T>>::insert(&product_id, <timestamp::Module<T>>::now());
+            // Queue the product for its shipments to be held (2 DB writes, 1 DB read)
+            let tail = RecallQueueTail::get();
+            RecallQueue::insert(tail, &product_id);
+            RecallQueueTail::put(tail.wrapping_add(1));
+
+            Self::deposit_event(RawEvent::ProductRecalled(who, product_id));
+
+            Ok(())
+        }
+
+        /// Registers a sensor device for an organization.
+        /// Readings from the device must be signed with the matching private key.
+        #[weight = T::WeightInfo::register_device()]
+        pub fn register_device(origin, id: DeviceId, public_key: DevicePublicKey, owner: T::AccountId) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::validate_role(&who, &[Role::SensorGateway])?;
+
+            Self::validate_device_id(&id)?;
+            Self::validate_owner_or_delegate(&who, &owner)?;
+            ensure!(!Devices::<T>::contains_key(&id), Error::<T>::DeviceAlreadyRegistered);
+
+            Devices::<T>::insert(&id, Device {
+                id: id.clone(),
+                public_key,
+                owner: owner.clone(),
+                revoked: false,
+            });
+
+            Self::deposit_event(RawEvent::DeviceRegistered(who, id, owner));
+
+            Ok(())
+        }
+
+        /// Revokes a device, e.g. when it's lost or its key is compromised
+        #[weight = T::WeightInfo::revoke_device()]
+        pub fn revoke_device(origin, id: DeviceId) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::validate_role(&who, &[Role::SensorGateway])?;
+
+            let device = Devices::<T>::get(&id).ok_or(Error::<T>::DeviceIsUnregistered)?;
+            Self::validate_owner_or_delegate(&who, &device.owner)?;
+            ensure!(!device.revoked, Error::<T>::DeviceIsRevoked);
+
+            Devices::<T>::insert(&id, Device { revoked: true, ..device });
+
+            Self::deposit_event(RawEvent::DeviceRevoked(who, id));
+
+            Ok(())
+        }
+
+        /// Loads shipments in a container shipment. Events recorded on the container then
+        /// apply to the loaded shipments, until a `ShipmentDisaggregation` event unloads them.
+        /// The sender must be the custodian of the container and of every shipment.
+        #[weight = T::WeightInfo::aggregate_shipments(children.len() as u32)]
+        pub fn aggregate_shipments(origin, container_id: ShipmentId, children: Vec<ShipmentId>) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::validate_role(&who, &[Role::Shipper, Role::Carrier])?;
+
+            // Validate extrinsic data (no storage access)
+            ensure!(!children.is_empty(), Error::<T>::NoShipmentsToAggregate);
+            let mut unique = children.clone();
+            unique.sort();
+            unique.dedup();
+            ensure!(unique.len() == children.len(), Error::<T>::DuplicateShipmentInAggregation);
+
+            // Check container (3 DB reads)
+            let container = <Shipments<T>>::get(&container_id).ok_or(Error::<T>::ShipmentIsUnknown)?;
+            ensure!(container.custodian == who, Error::<T>::SenderIsNotCustodian);
+            ensure!(!container.status.is_final(), Error::<T>::ShipmentIsClosed);
+            ensure!(!ParentOf::contains_key(&container_id), Error::<T>::ShipmentIsAlreadyAggregated);
+            let mut loaded = ChildrenOf::get(&container_id);
+            ensure!(
+                loaded.len() + children.len() <= CONTAINER_MAX_SHIPMENTS,
+                Error::<T>::ContainerHasTooManyShipments
+            );
+
+            // Check shipments (3 DB reads per shipment)
+            for child_id in children.iter() {
+                let child = <Shipments<T>>::get(child_id).ok_or(Error::<T>::ShipmentIsUnknown)?;
+                ensure!(child.custodian == who, Error::<T>::SenderIsNotCustodian);
+                ensure!(!child.status.is_final(), Error::<T>::ShipmentIsClosed);
+                ensure!(
+                    child_id != &container_id && !ChildrenOf::contains_key(child_id),
+                    Error::<T>::ShipmentIsContainer
+                );
+                ensure!(!ParentOf::contains_key(child_id), Error::<T>::ShipmentIsAlreadyAggregated);
+            }
 
             // Storage writes
             // --------------
-            EventCount::put(event_idx);
-            <AllEvents<T>>::insert(event_idx, event);
-            EventIndices::insert(&event_id, event_idx);
-            EventsOfShipment::append(&shipment_id, event_idx);
-
-            Self::deposit_event(RawEvent::ShippingEventRecorded(who, event_id, shipment_id.clone(), event_type.clone()));
-
-            match event_type {
-                ShippingEventType::SensorReading => { /* Do nothing */ },
-                _ => {
-                    shipment = match event_type {
-                        ShippingEventType::ShipmentPickup => shipment.pickup(),
-                        ShippingEventType::ShipmentDelivery => shipment.deliver(<timestamp::Module<T>>::now()),
-                        _ => unreachable!()
-                    };
-                    let new_status = shipment.status.clone();
-                    <Shipments<T>>::insert(&shipment_id, shipment);
-                    Self::deposit_event(RawEvent::ShipmentStatusUpdated(shipment_id, new_status));
-                },
+            for child_id in children.iter() {
+                ParentOf::insert(child_id, &container_id);
             }
+            loaded.extend(children.iter().cloned());
+            ChildrenOf::insert(&container_id, loaded);
+
+            Self::deposit_event(RawEvent::ShipmentsAggregated(who, container_id, children));
+
+            Ok(())
+        }
+
+        /// Declares the area a shipment is expected to be delivered in.
+        /// Deliveries are then checked against it by `record_event`.
+        #[weight = T::WeightInfo::set_geofence()]
+        pub fn set_geofence(origin, shipment_id: ShipmentId, geofence: Geofence) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::validate_role(&who, &[Role::Shipper])?;
+
+            ensure!(geofence.is_valid(), Error::<T>::InvalidGeofence);
+
+            let shipment = <Shipments<T>>::get(&shipment_id).ok_or(Error::<T>::ShipmentIsUnknown)?;
+            Self::validate_owner_or_delegate(&who, &shipment.owner)?;
+
+            Geofences::insert(&shipment_id, geofence);
+
+            Self::deposit_event(RawEvent::GeofenceSet(who, shipment_id));
 
             Ok(())
         }
 
+        /// Sets the acceptable range for a type of reading on a shipment.
+        /// Readings outside this range are reported as excursions by `record_event`.
+        #[weight = T::WeightInfo::set_threshold_rule()]
+        pub fn set_threshold_rule(origin, shipment_id: ShipmentId, reading_type: ReadingType, rule: ReadingThreshold) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::validate_role(&who, &[Role::Shipper])?;
+
+            ensure!(rule.is_valid(), Error::<T>::InvalidThresholdRule);
+
+            // Only the shipper may set rules (1-2 DB reads)
+            let shipment = <Shipments<T>>::get(&shipment_id).ok_or(Error::<T>::ShipmentIsUnknown)?;
+            Self::validate_owner_or_delegate(&who, &shipment.owner)?;
+
+            ThresholdRules::insert(&shipment_id, reading_type, rule.clone());
+
+            Self::deposit_event(RawEvent::ThresholdRuleSet(who, shipment_id, reading_type, rule));
+
+            Ok(())
+        }
+
+        /// Removes a threshold rule from a shipment
+        #[weight = T::WeightInfo::remove_threshold_rule()]
+        pub fn remove_threshold_rule(origin, shipment_id: ShipmentId, reading_type: ReadingType) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::validate_role(&who, &[Role::Shipper])?;
+
+            let shipment = <Shipments<T>>::get(&shipment_id).ok_or(Error::<T>::ShipmentIsUnknown)?;
+            Self::validate_owner_or_delegate(&who, &shipment.owner)?;
+            ensure!(
+                ThresholdRules::contains_key(&shipment_id, reading_type),
+                Error::<T>::ThresholdRuleIsUnknown
+            );
+
+            ThresholdRules::remove(&shipment_id, reading_type);
+
+            Self::deposit_event(RawEvent::ThresholdRuleRemoved(who, shipment_id, reading_type));
+
+            Ok(())
+        }
 	}
 }
 
@@ -221,7 +1119,7 @@ impl<T: Trait> AccountSet for Module<T> {
 	type AccountId = T::AccountId;
 
 	fn accounts() -> BTreeSet<T::AccountId> {
-		<Members<T> as IterableStorageMap<T::AccountId, ()>>::iter()
+		<Members<T> as IterableStorageMap<T::AccountId, Vec<Role>>>::iter()
 			.map(|(acct, _)| acct)
 			.collect::<BTreeSet<_>>()
 	}
@@ -233,16 +1131,500 @@ impl<T: Trait> Module<T> {
 		ShipmentBuilder::<T::AccountId, T::Moment>::default()
 	}
 
+	/// Weight of recording `event`. Events reaching the shipments loaded in a container are
+	/// charged for a full container, as the weight can't depend on storage.
+	pub fn event_weight(event: &ShippingEvent<T::Moment>) -> Weight {
+		let reaches_children = event.event_type.propagates_to_children()
+			|| event.event_type == ShippingEventType::ShipmentDisaggregation;
+		let children = if reaches_children { CONTAINER_MAX_SHIPMENTS as u32 } else { 0 };
+		T::WeightInfo::record_event(event.readings.len() as u32, children)
+	}
+
+	/// Returns the shipments of an organization, from index `start` and up to `limit` of them
+	pub fn shipments_of_org(owner: &T::AccountId, start: u32, limit: u32) -> Vec<ShipmentId> {
+		let end = ShipmentCountOfOrganization::<T>::get(owner).min(start.saturating_add(limit));
+		(start..end)
+			.filter_map(|idx| <ShipmentsOfOrganization<T>>::get(owner, idx))
+			.collect()
+	}
+
+	// Adds a shipment to an organization's index (1 DB read, 3 DB writes)
+	fn index_shipment_of_org(owner: &T::AccountId, shipment_id: &[u8]) {
+		let idx = ShipmentCountOfOrganization::<T>::get(owner);
+		<ShipmentsOfOrganization<T>>::insert(owner, idx, shipment_id);
+		<ShipmentIndexOfOrganization<T>>::insert(owner, shipment_id, idx);
+		ShipmentCountOfOrganization::<T>::insert(owner, idx + 1);
+	}
+
+	// Moves a shipment from an organization's index to another's. It leaves a gap in the
+	// former's, so that the other shipments keep their index while being paged through.
+	// (2 DB reads, 5 DB writes)
+	fn move_shipment(shipment_id: &[u8], from: &T::AccountId, to: &T::AccountId) {
+		if let Some(idx) = <ShipmentIndexOfOrganization<T>>::take(from, shipment_id) {
+			<ShipmentsOfOrganization<T>>::remove(from, idx);
+		}
+		Self::index_shipment_of_org(to, shipment_id);
+	}
+
+	/// Returns the open shipments carrying a product, as (shipment, status, custodian), from the
+	/// index `start` on and up to `limit` of them. Also returns the index to resume from, if any.
+	pub fn product_locations(
+		product_id: &[u8],
+		start: u32,
+		limit: u32,
+	) -> (Vec<(ShipmentId, ShipmentStatus, T::AccountId)>, Option<u32>) {
+		let count = ShipmentCountOfProduct::get(product_id);
+		let mut locations = Vec::new();
+		let mut idx = start;
+		while idx < count && (locations.len() as u32) < limit {
+			let shipment = ShipmentsOfProduct::get(product_id, idx).and_then(|id| <Shipments<T>>::get(&id));
+			if let Some(shipment) = shipment {
+				if !shipment.status.is_final() {
+					locations.push((shipment.id, shipment.status, shipment.custodian));
+				}
+			}
+			idx += 1;
+		}
+		let next = if idx < count { Some(idx) } else { None };
+		(locations, next)
+	}
+
+	// Adds a shipment to the index of its products
+	fn index_products(shipment_id: &[u8], products: &[ProductId]) {
+		for product in products {
+			let idx = ShipmentCountOfProduct::get(product);
+			ShipmentsOfProduct::insert(product, idx, shipment_id);
+			ShipmentCountOfProduct::insert(product, idx + 1);
+		}
+	}
+
+	/// Returns the indices of the events of a shipment, from index `start` and up to `limit` of them.
+	/// Pruned events are skipped.
+	pub fn events_of_shipment(shipment_id: &[u8], start: u32, limit: u32) -> Vec<ShippingEventIndex> {
+		let end = EventCountOfShipment::get(shipment_id).min(start.saturating_add(limit));
+		(start..end)
+			.filter_map(|idx| EventsOfShipment::get(shipment_id, idx))
+			.collect()
+	}
+
+	/// Returns the shipments of an organization that are in `status`, if given.
+	/// The organization's index is scanned from `start` until `limit` shipments are found,
+	/// the index to resume from is returned along if the scan stopped early.
+	pub fn shipments_of_org_by_status(
+		owner: &T::AccountId,
+		status: Option<ShipmentStatus>,
+		start: u32,
+		limit: u32,
+	) -> (Vec<Shipment<T::AccountId, T::Moment>>, Option<u32>) {
+		let count = ShipmentCountOfOrganization::<T>::get(owner);
+		let mut shipments = Vec::new();
+		let mut idx = start;
+		while idx < count && (shipments.len() as u32) < limit {
+			let shipment = <ShipmentsOfOrganization<T>>::get(owner, idx).and_then(|id| <Shipments<T>>::get(&id));
+			if let Some(shipment) = shipment {
+				if status.as_ref().map_or(true, |status| *status == shipment.status) {
+					shipments.push(shipment);
+				}
+			}
+			idx += 1;
+		}
+		let next = if idx < count { Some(idx) } else { None };
+		(shipments, next)
+	}
+
+	/// Returns the events recorded for a shipment, in order. Pruned events are skipped.
+	pub fn shipment_timeline(shipment_id: &[u8]) -> Vec<ShippingEvent<T::Moment>> {
+		Self::events_of_shipment(shipment_id, 0, u32::max_value())
+			.into_iter()
+			.filter_map(|idx| <AllEvents<T>>::get(idx))
+			.collect()
+	}
+
+	/// Returns the readings of a type recorded for a shipment and taken between `from`
+	/// and `to`, as (timestamp, value)
+	pub fn reading_series(
+		shipment_id: &[u8],
+		reading_type: ReadingType,
+		from: T::Moment,
+		to: T::Moment,
+	) -> Vec<(T::Moment, Decimal)> {
+		Self::shipment_timeline(shipment_id)
+			.into_iter()
+			.flat_map(|event| event.readings)
+			.filter(|reading| reading.reading_type == reading_type)
+			.filter(|reading| reading.timestamp >= from && reading.timestamp <= to)
+			.map(|reading| (reading.timestamp, reading.value))
+			.collect()
+	}
+
+	fn schedule_pruning(shipment_id: &[u8], delivered: T::Moment) {
+		let tail = PruningQueueTail::get();
+		<PruningQueue<T>>::insert(tail, (shipment_id.to_vec(), delivered.saturating_add(T::RetentionPeriod::get())));
+		PruningQueueTail::put(tail.wrapping_add(1));
+	}
+
+	/// Prunes the events of shipments delivered more than `RetentionPeriod` ago, up to
+	/// `MaxPrunedEventsPerBlock` events. Before pruning a shipment's events, the Merkle
+	/// root of the events it recorded is archived.
+	///
+	/// Events recorded on a container are only pruned with the container. The shipments
+	/// loaded in it just drop them from their index.
+	pub fn prune_events(now: T::Moment) -> Weight {
+		let mut budget = T::MaxPrunedEventsPerBlock::get() as usize;
+		let (mut reads, mut writes): (Weight, Weight) = (0, 0);
+
+		while budget > 0 {
+			let head = PruningQueueHead::get();
+			reads += 2;
+			let shipment_id = match <PruningQueue<T>>::get(head) {
+				Some((shipment_id, prune_after)) if prune_after <= now => shipment_id,
+				_ => break,
+			};
+
+			let count = EventCountOfShipment::get(&shipment_id);
+			let cursor = PruningCursor::get();
+			reads += 3;
+			if !<ArchivedRoots<T>>::contains_key(&shipment_id) {
+				let leaves = (0..count)
+					.filter_map(|idx| EventsOfShipment::get(&shipment_id, idx))
+					.filter_map(|idx| <AllEvents<T>>::get(idx))
+					.filter(|event| event.shipment_id == shipment_id)
+					.map(|event| T::Hashing::hash_of(&event))
+					.collect::<Vec<_>>();
+				let root = merkle_root::<T::Hashing>(&leaves);
+				<ArchivedRoots<T>>::insert(&shipment_id, root);
+				reads += 2 * count as Weight;
+				writes += 1;
+				Self::deposit_event(RawEvent::ShipmentEventsArchived(shipment_id.clone(), root, leaves.len() as u32));
+			}
+
+			let pruned = budget.min(count.saturating_sub(cursor) as usize) as u32;
+			for idx in cursor..cursor + pruned {
+				reads += 2;
+				writes += 1;
+				let event = EventsOfShipment::take(&shipment_id, idx).and_then(|idx| {
+					<AllEvents<T>>::get(idx).map(|event| (idx, event))
+				});
+				if let Some((event_idx, event)) = event {
+					if event.shipment_id == shipment_id {
+						<AllEvents<T>>::remove(event_idx);
+						EventIndices::remove(&event.id);
+						writes += 2;
+					}
+				}
+			}
+			budget -= pruned as usize;
+
+			writes += 1;
+			if cursor + pruned >= count {
+				EventCountOfShipment::remove(&shipment_id);
+				<PruningQueue<T>>::remove(head);
+				PruningQueueHead::put(head.wrapping_add(1));
+				PruningCursor::kill();
+				writes += 3;
+			} else {
+				PruningCursor::put(cursor + pruned);
+			}
+		}
+
+		T::DbWeight::get().reads_writes(reads, writes)
+	}
+
+	/// Queues the deadline of a shipment in its bucket
+	fn queue_deadline(shipment_id: &[u8], deadline: T::Moment, now: T::Moment) {
+		let size = T::DeadlineBucketSize::get();
+		let queued = QueuedDeadlines::get();
+		// Without queued deadlines, the cursor skips the buckets elapsed since the last one
+		if queued == 0 {
+			<DeadlineCursor<T>>::put((now / size, 0));
+		}
+		let bucket = deadline / size;
+		let idx = <DeadlineCountOfBucket<T>>::get(bucket);
+		<DeadlineBuckets<T>>::insert(bucket, idx, shipment_id);
+		<DeadlineCountOfBucket<T>>::insert(bucket, idx + 1);
+		QueuedDeadlines::put(queued + 1);
+	}
+
+	/// Reports the shipments whose deadline passed before `now` while still pending or
+	/// in transit, and refunds their payment. Buckets are checked in order once
+	/// elapsed, up to `MaxOverdueChecksPerBlock` deadlines or empty buckets. A deadline is only
+	/// checked once, shipments delivered or closed by then are simply dequeued.
+	pub fn check_deadlines(now: T::Moment) {
+		let mut queued = QueuedDeadlines::get();
+		if queued == 0 {
+			return;
+		}
+
+		let size = T::DeadlineBucketSize::get();
+		let (mut bucket, mut idx) = <DeadlineCursor<T>>::get();
+		let mut count = <DeadlineCountOfBucket<T>>::get(bucket);
+		let mut budget = T::MaxOverdueChecksPerBlock::get();
+		while budget > 0 && queued > 0 && bucket.saturating_add(One::one()).saturating_mul(size) <= now {
+			budget -= 1;
+			if idx < count {
+				if let Some(shipment_id) = <DeadlineBuckets<T>>::take(bucket, idx) {
+					queued -= 1;
+					Self::check_deadline(shipment_id, bucket.saturating_mul(size));
+				}
+				idx += 1;
+			}
+			// Move on once a bucket is checked, empty ones cost a check as well
+			if idx >= count {
+				<DeadlineCountOfBucket<T>>::remove(bucket);
+				bucket = bucket.saturating_add(One::one());
+				idx = 0;
+				count = <DeadlineCountOfBucket<T>>::get(bucket);
+			}
+		}
+		<DeadlineCursor<T>>::put((bucket, idx));
+		QueuedDeadlines::put(queued);
+	}
+
+	// Reports a shipment if still open when its deadline passed
+	fn check_deadline(shipment_id: ShipmentId, bucket_start: T::Moment) {
+		let overdue = <Shipments<T>>::get(&shipment_id).map_or(false, |shipment| match shipment.status {
+			ShipmentStatus::Pending | ShipmentStatus::InTransit => true,
+			_ => false,
+		});
+		if overdue {
+			if let Some(event) = Self::settle_payment(&shipment_id, false) {
+				Self::deposit_event(event);
+			}
+			let deadline = <DeliveryDeadlines<T>>::get(&shipment_id).unwrap_or(bucket_start);
+			Self::deposit_event(RawEvent::ShipmentOverdue(shipment_id, deadline));
+		}
+	}
+
+	/// Settles the payment held for a shipment, if any, paying the carrier or refunding the
+	/// payer. Returns the event reporting it.
+	fn settle_payment(shipment_id: &[u8], pay_carrier: bool) -> Option<Event<T>> {
+		let payment = <Payments<T>>::take(shipment_id)?;
+		if pay_carrier {
+			T::Escrow::release(&payment.payer, &payment.carrier, payment.amount);
+			Some(RawEvent::PaymentReleased(shipment_id.to_vec(), payment.carrier, payment.amount))
+		} else {
+			T::Escrow::refund(&payment.payer, payment.amount);
+			Some(RawEvent::PaymentRefunded(shipment_id.to_vec(), payment.payer, payment.amount))
+		}
+	}
+
+	/// Opens the acknowledgment window of a delivery recorded `now`
+	fn await_acknowledgment(shipment_id: &[u8], now: T::Moment) {
+		let expiry = now.saturating_add(T::AcknowledgmentWindow::get());
+		<PendingAcknowledgments<T>>::insert(shipment_id, (now, expiry));
+		let tail = AcknowledgmentQueueTail::get();
+		<AcknowledgmentQueue<T>>::insert(tail, (shipment_id.to_vec(), expiry));
+		AcknowledgmentQueueTail::put(tail.wrapping_add(1));
+	}
+
+	/// Completes the delivery of a shipment that was acknowledged, or not disputed in time
+	fn finalize_delivery(shipment_id: &[u8], shipment: Shipment<T::AccountId, T::Moment>, delivered: T::Moment) {
+		<PendingAcknowledgments<T>>::remove(shipment_id);
+		<Shipments<T>>::insert(shipment_id, shipment.deliver(delivered));
+		Self::schedule_pruning(shipment_id, <timestamp::Module<T>>::now());
+		Self::record_lateness(shipment_id, delivered);
+
+		if let Some(event) = Self::settle_payment(shipment_id, true) {
+			Self::deposit_event(event);
+		}
+		Self::deposit_event(RawEvent::ShipmentStatusUpdated(shipment_id.to_vec(), ShipmentStatus::Delivered));
+	}
+
+	/// Completes the deliveries whose acknowledgment window expired before `now`,
+	/// up to `MaxExpiredAcknowledgmentsPerBlock` of them
+	pub fn expire_acknowledgments(now: T::Moment) {
+		for _ in 0..T::MaxExpiredAcknowledgmentsPerBlock::get() {
+			let head = AcknowledgmentQueueHead::get();
+			let (shipment_id, expiry) = match <AcknowledgmentQueue<T>>::get(head) {
+				Some((shipment_id, expiry)) if expiry < now => (shipment_id, expiry),
+				_ => break,
+			};
+			<AcknowledgmentQueue<T>>::remove(head);
+			AcknowledgmentQueueHead::put(head.wrapping_add(1));
+
+			// Deliveries confirmed or disputed meanwhile are skipped. A disputed shipment may
+			// also be delivered again, under a later window. Recalled goods are never accepted,
+			// the recall holds their shipment.
+			let pending = <PendingAcknowledgments<T>>::get(&shipment_id);
+			match (pending, <Shipments<T>>::get(&shipment_id)) {
+				(Some((delivered, pending_expiry)), Some(shipment))
+					if pending_expiry == expiry && !Self::carries_recalled_product(&shipment.products) =>
+				{
+					Self::finalize_delivery(&shipment_id, shipment, delivered);
+					Self::deposit_event(RawEvent::DeliveryAcknowledgmentExpired(shipment_id));
+				}
+				_ => {}
+			}
+		}
+	}
+
+	/// Holds the shipments of recalled products, checking up to `MaxRecalledShipmentsPerBlock`
+	/// of them. Products are recalled in order, from the head of the queue.
+	pub fn hold_recalled_shipments() -> Weight {
+		let mut budget = T::MaxRecalledShipmentsPerBlock::get();
+		let (start_head, start_cursor) = (RecallQueueHead::get(), RecallCursor::get());
+		let (mut head, mut cursor) = (start_head, start_cursor);
+		let (mut reads, mut writes): (Weight, Weight) = (2, 0);
+
+		while budget > 0 {
+			reads += 1;
+			let product_id = match RecallQueue::get(head) {
+				Some(product_id) => product_id,
+				None => break,
+			};
+			reads += 1;
+			let count = ShipmentCountOfProduct::get(&product_id);
+			while budget > 0 && cursor < count {
+				budget -= 1;
+				reads += 2;
+				if let Some(shipment_id) = ShipmentsOfProduct::get(&product_id, cursor) {
+					if Self::hold_recalled_shipment(&product_id, shipment_id) {
+						writes += 2;
+					}
+				}
+				cursor += 1;
+			}
+			if cursor < count {
+				break;
+			}
+			// All of the product's shipments were checked, dequeuing it counts as a check
+			budget = budget.saturating_sub(1);
+			RecallQueue::remove(head);
+			writes += 1;
+			head = head.wrapping_add(1);
+			cursor = 0;
+		}
+
+		if head != start_head {
+			RecallQueueHead::put(head);
+			writes += 1;
+		}
+		if cursor != start_cursor {
+			RecallCursor::put(cursor);
+			writes += 1;
+		}
+		T::DbWeight::get().reads_writes(reads, writes)
+	}
+
+	// Holds an open shipment carrying a recalled product. A delivery awaiting acknowledgment
+	// is held as well, its acknowledgment window closes.
+	// Returns whether the shipment was held.
+	fn hold_recalled_shipment(product_id: &[u8], shipment_id: ShipmentId) -> bool {
+		let shipment = match <Shipments<T>>::get(&shipment_id) {
+			Some(shipment) => shipment,
+			None => return false,
+		};
+		match shipment.status {
+			ShipmentStatus::Held => return false,
+			ref status if status.is_final() => return false,
+			ShipmentStatus::AwaitingAcknowledgment => <PendingAcknowledgments<T>>::remove(&shipment_id),
+			_ => {}
+		}
+		<Shipments<T>>::insert(&shipment_id, shipment.update_status(ShipmentStatus::Held));
+
+		Self::deposit_event(RawEvent::ShipmentRecalled(product_id.to_vec(), shipment_id.clone()));
+		Self::deposit_event(RawEvent::ShipmentStatusUpdated(shipment_id, ShipmentStatus::Held));
+		true
+	}
+
+	fn record_lateness(shipment_id: &[u8], delivered: T::Moment) {
+		if let Some(deadline) = <DeliveryDeadlines<T>>::get(shipment_id) {
+			<DeliveryLateness<T>>::insert(shipment_id, delivered.saturating_sub(deadline));
+		}
+	}
+
+	/// Checks an event may be recorded on its own. Custody only changes hands once the
+	/// receiving party accepts it, and ownership through `transfer_shipment`.
+	pub fn validate_recordable(event_type: &ShippingEventType) -> Result<(), Error<T>> {
+		ensure!(*event_type != ShippingEventType::CustodyHandoff, Error::<T>::CustodyHandoffMustBeAccepted);
+		ensure!(*event_type != ShippingEventType::OwnershipTransfer, Error::<T>::ShipmentTransferRequired);
+		Ok(())
+	}
+
+	/// Checks that `who` has one of `roles`
+	pub fn validate_role(who: &T::AccountId, roles: &[Role]) -> Result<(), Error<T>> {
+		Self::validate_granted_roles(&Members::<T>::get(who), roles)
+	}
+
+	/// Checks that one of the `granted` roles is among `roles`
+	pub fn validate_granted_roles(granted: &[Role], roles: &[Role]) -> Result<(), Error<T>> {
+		ensure!(roles.iter().any(|role| granted.contains(role)), Error::<T>::SenderLacksRole);
+		Ok(())
+	}
+
 	pub fn validate_identifier(id: &[u8]) -> Result<(), Error<T>> {
 		// Basic identifier validation
 		ensure!(!id.is_empty(), Error::<T>::InvalidOrMissingIdentifier);
 		ensure!(
-            id.len() <= IDENTIFIER_MAX_LENGTH,
+            id.len() <= T::MaxIdentifierLength::get() as usize,
             Error::<T>::InvalidOrMissingIdentifier
         );
 		Ok(())
 	}
 
+	/// Checks that `who` may act for the `owner` organization, returning the delegation
+	/// that was used when `who` isn't the owner itself.
+	pub fn validate_owner_or_delegate(
+		who: &T::AccountId,
+		owner: &T::AccountId,
+	) -> Result<Option<Relation<T::AccountId>>, Error<T>> {
+		if who == owner {
+			return Ok(None);
+		}
+		let relation = Relation::new(owner.clone(), who.clone());
+		ensure!(
+            T::DelegationSource::contains(&relation),
+            Error::<T>::SenderIsNotOwnerOrDelegate
+        );
+		Ok(Some(relation))
+	}
+
+	pub fn validate_device_id(id: &[u8]) -> Result<(), Error<T>> {
+		ensure!(!id.is_empty(), Error::<T>::InvalidOrMissingIdentifier);
+		ensure!(
+            id.len() <= DEVICE_ID_MAX_LENGTH,
+            Error::<T>::InvalidOrMissingIdentifier
+        );
+		Ok(())
+	}
+
+	pub fn validate_readings(shipment_id: &[u8], readings: &[Reading<T::Moment>]) -> Result<(), Error<T>> {
+		for reading in readings {
+			let device = Devices::<T>::get(&reading.device_id).ok_or(Error::<T>::DeviceIsUnregistered)?;
+			ensure!(!device.revoked, Error::<T>::DeviceIsRevoked);
+			ensure!(
+				reading.signature.verify(&reading.signing_payload(shipment_id)[..], &device.public_key),
+				Error::<T>::InvalidReadingSignature
+			);
+		}
+		Ok(())
+	}
+
+	/// Checks a delivery location against the shipment's geofence.
+	/// Returns whether the delivery must be flagged as outside of the geofence,
+	/// or an error when such deliveries are rejected.
+	pub fn validate_delivery_location(shipment_id: &[u8], location: &Option<ReadPoint>) -> Result<bool, Error<T>> {
+		let geofence = match Geofences::get(shipment_id) {
+			Some(geofence) => geofence,
+			None => return Ok(false),
+		};
+		let reject = T::RejectDeliveriesOutsideGeofence::get();
+		match location {
+			None if reject => Err(Error::<T>::DeliveryLocationMissing),
+			Some(point) if reject && !geofence.contains(point) => Err(Error::<T>::DeliveryOutsideGeofence),
+			None => Ok(true),
+			Some(point) => Ok(!geofence.contains(point)),
+		}
+	}
+
+	pub fn validate_read_point(location: &Option<ReadPoint>) -> Result<(), Error<T>> {
+		if let Some(point) = location {
+			ensure!(point.has_valid_latitude(), Error::<T>::LatitudeOutOfRange);
+			ensure!(point.has_valid_longitude(), Error::<T>::LongitudeOutOfRange);
+		}
+		Ok(())
+	}
+
 	pub fn validate_new_shipment(id: &[u8]) -> Result<(), Error<T>> {
 		// Shipment existence check
 		ensure!(
@@ -252,10 +1634,114 @@ impl<T: Trait> Module<T> {
 		Ok(())
 	}
 
+	pub fn validate_org_capacity(owner: &T::AccountId) -> Result<(), Error<T>> {
+		ensure!(
+            ShipmentCountOfOrganization::<T>::get(owner) < T::MaxShipmentsPerOrganization::get(),
+            Error::<T>::OrganizationHasTooManyShipments
+        );
+		Ok(())
+	}
+
 	pub fn validate_shipment_products(props: &[ProductId]) -> Result<(), Error<T>> {
 		ensure!(
             props.len() <= SHIPMENT_MAX_PRODUCTS,
             Error::<T>::ShipmentHasTooManyProducts,
+        );
+		for product in props {
+			ensure!(T::ProductRegistry::contains(product), Error::<T>::ProductIsUnknown);
+			ensure!(!<RecalledProducts<T>>::contains_key(product), Error::<T>::ProductIsRecalled);
+		}
+		Ok(())
+	}
+
+	pub fn validate_status_transition(
+		status: &ShipmentStatus,
+		event_type: &ShippingEventType,
+	) -> Result<ShipmentStatus, Error<T>> {
+		// The transition table lives with the status type, we only map refusals to errors
+		status.next(event_type).ok_or_else(|| match (status, event_type) {
+			(ShipmentStatus::Delivered, _) => Error::<T>::ShipmentHasBeenDelivered,
+			(ShipmentStatus::InTransit, ShippingEventType::ShipmentPickup) => Error::<T>::ShipmentIsInTransit,
+			_ => Error::<T>::ShipmentStatusTransitionNotAllowed,
+		})
+	}
+
+	/// Returns whether a shipment carries a recalled product (1 DB read per product)
+	pub fn carries_recalled_product(products: &[ProductId]) -> bool {
+		products.iter().any(|product| <RecalledProducts<T>>::contains_key(product))
+	}
+
+	/// Checks a shipment carrying a recalled product doesn't move on. Its status may only
+	/// change to hold, cancel or return it.
+	pub fn validate_recall(
+		recalled: bool,
+		status: &ShipmentStatus,
+		new_status: &ShipmentStatus,
+		event_type: &ShippingEventType,
+	) -> Result<(), Error<T>> {
+		let allowed = match event_type {
+			ShippingEventType::ShipmentHold
+			| ShippingEventType::ShipmentCancellation
+			| ShippingEventType::ShipmentReturn => true,
+			_ => new_status == status,
+		};
+		ensure!(!recalled || allowed, Error::<T>::ShipmentIsRecalled);
+		Ok(())
+	}
+
+	/// Returns the readings that fall outside the shipment's threshold rules
+	pub fn find_excursions(
+		shipment_id: &[u8],
+		readings: &[Reading<T::Moment>],
+	) -> Vec<(ReadingType, Decimal)> {
+		readings
+			.iter()
+			.filter(|reading| {
+				ThresholdRules::get(shipment_id, reading.reading_type)
+					.map_or(false, |rule| rule.is_excursion(reading.value))
+			})
+			.map(|reading| (reading.reading_type, reading.value))
+			.collect()
+	}
+
+	/// Checks a shipping event is dated after the shipment's registration and latest event,
+	/// and not in the future. Its readings must be taken between registration and the event.
+	pub fn validate_event_timing(
+		now: T::Moment,
+		registered: T::Moment,
+		latest_event: Option<T::Moment>,
+		event: &ShippingEvent<T::Moment>,
+	) -> Result<(), Error<T>> {
+		let latest = now.saturating_add(T::MaxClockSkew::get());
+		ensure!(event.timestamp <= latest, Error::<T>::ShippingEventInFuture);
+		ensure!(event.timestamp >= registered, Error::<T>::ShippingEventBeforeRegistration);
+		Self::validate_event_order(latest_event, event.timestamp)?;
+
+		for reading in event.readings.iter() {
+			ensure!(reading.timestamp >= registered, Error::<T>::ReadingBeforeRegistration);
+			ensure!(reading.timestamp <= event.timestamp, Error::<T>::ReadingAfterShippingEvent);
+		}
+		Ok(())
+	}
+
+	/// Checks an event dated `timestamp` doesn't precede the shipment's latest event
+	pub fn validate_event_order(latest_event: Option<T::Moment>, timestamp: T::Moment) -> Result<(), Error<T>> {
+		if let Some(latest) = latest_event {
+			ensure!(timestamp >= latest, Error::<T>::ShippingEventOutOfOrder);
+		}
+		Ok(())
+	}
+
+	fn latest_event_timestamp(shipment_id: &[u8]) -> Option<T::Moment> {
+		let count = EventCountOfShipment::get(shipment_id);
+		let idx = EventsOfShipment::get(shipment_id, count.checked_sub(1)?)?;
+		<AllEvents<T>>::get(idx).map(|event| event.timestamp)
+	}
+
+	pub fn validate_event_capacity(event_count: u32) -> Result<(), Error<T>> {
+		ensure!(
+            event_count < T::MaxEventsPerShipment::get(),
+            Error::<T>::ShipmentHasTooManyEvents
         );
 		Ok(())
 	}
@@ -279,13 +1765,14 @@ pub struct ShipmentBuilder<AccountId, Moment>
 {
 	id: ShipmentId,
 	owner: AccountId,
+	consignee: Option<AccountId>,
 	products: Vec<ProductId>,
 	registered: Moment,
 }
 
 impl<AccountId, Moment> ShipmentBuilder<AccountId, Moment>
 	where
-		AccountId: Default,
+		AccountId: Default + Clone,
 		Moment: Default,
 {
 	pub fn identified_by(mut self, id: ShipmentId) -> Self {
@@ -298,6 +1785,11 @@ impl<AccountId, Moment> ShipmentBuilder<AccountId, Moment>
 		self
 	}
 
+	pub fn consigned_to(mut self, consignee: Option<AccountId>) -> Self {
+		self.consignee = consignee;
+		self
+	}
+
 	pub fn with_products(mut self, products: Vec<ProductId>) -> Self {
 		self.products = products;
 		self
@@ -311,11 +1803,14 @@ impl<AccountId, Moment> ShipmentBuilder<AccountId, Moment>
 	pub fn build(self) -> Shipment<AccountId, Moment> {
 		Shipment::<AccountId, Moment> {
 			id: self.id,
+			custodian: self.owner.clone(),
 			owner: self.owner,
+			consignee: self.consignee,
 			products: self.products,
 			registered: self.registered,
 			status: ShipmentStatus::Pending,
 			delivered: None,
+			excursions: 0,
 		}
 	}
 }
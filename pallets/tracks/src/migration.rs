@@ -0,0 +1,451 @@
+//! Storage migrations of the tracks pallet.
+//!
+//! Each migration re-encodes values written by a previous release of the pallet, and bumps
+//! `StorageVersion` so that it only ever runs once.
+
+use frame_support::{
+	codec::{Decode, Encode},
+	storage::{
+		migration::{put_storage_value, take_storage_value, StorageIterator},
+		IterableStorageDoubleMap, IterableStorageMap,
+	},
+	traits::Get,
+	weights::Weight,
+	sp_runtime::RuntimeDebug,
+};
+use fixed::types::{U16F16, U48F16};
+use sp_std::{cell::Cell, prelude::*};
+
+use bluefn_util::catalog::ProductId;
+use bluefn_util::shipment::*;
+
+use crate::{
+	AllEvents, DeadlineBuckets, DeadlineCountOfBucket, DeadlineCursor, EventCountOfShipment, EventsOfShipment,
+	MemberCount, Members, Module, PendingTransfers, QueuedDeadlines, ReadingStatistics, Releases, Role,
+	ShipmentCountOfOrganization, ShipmentIndexOfOrganization, Shipments, ShipmentsOfOrganization, StorageVersion,
+	ThresholdRules, Trait,
+};
+
+// Prefix of the pallet's storage, as declared by `decl_storage`
+const MODULE_PREFIX: &[u8] = b"VecMap";
+// Length of a `blake2_128_concat` hash, before the key itself
+const BLAKE2_128_LEN: usize = 16;
+
+// Layouts of the V1 release
+// -------------------------
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ReadPointV1 {
+	pub latitude: U16F16,
+	pub longitude: U16F16,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ReadingV1<Moment> {
+	pub device_id: DeviceId,
+	pub reading_type: ReadingType,
+	pub timestamp: Moment,
+	pub value: U16F16,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ShippingEventV1<Moment> {
+	pub id: ShippingEventId,
+	pub event_type: ShippingEventType,
+	pub shipment_id: ShipmentId,
+	pub location: Option<ReadPointV1>,
+	pub readings: Vec<ReadingV1<Moment>>,
+	pub timestamp: Moment,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ShipmentV1<AccountId, Moment> {
+	pub id: ShipmentId,
+	pub owner: AccountId,
+	pub status: ShipmentStatus,
+	pub products: Vec<ProductId>,
+	pub registered: Moment,
+	pub delivered: Option<Moment>,
+}
+
+// Layouts of the V2 and V3 releases
+// ---------------------------------
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ShipmentV2<AccountId, Moment> {
+	pub id: ShipmentId,
+	pub owner: AccountId,
+	pub custodian: AccountId,
+	pub status: ShipmentStatus,
+	pub products: Vec<ProductId>,
+	pub registered: Moment,
+	pub delivered: Option<Moment>,
+	pub excursions: u32,
+}
+
+// Layouts of the V2 to V8 releases, with unsigned reading values
+// ---------------------------------------------------------------
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ReadingV2<Moment> {
+	pub device_id: DeviceId,
+	pub reading_type: ReadingType,
+	pub timestamp: Moment,
+	pub value: U16F16,
+	pub signature: DeviceSignature,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ShippingEventV2<Moment> {
+	pub id: ShippingEventId,
+	pub event_type: ShippingEventType,
+	pub shipment_id: ShipmentId,
+	pub location: Option<ReadPoint>,
+	pub readings: Vec<ReadingV2<Moment>>,
+	pub timestamp: Moment,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ReadingThresholdV2 {
+	pub min: Option<U16F16>,
+	pub max: Option<U16F16>,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ReadingStatsV2<Moment> {
+	pub count: u32,
+	pub min: U16F16,
+	pub max: U16F16,
+	pub sum: U48F16,
+	pub last_reading: Moment,
+}
+
+impl From<ReadPointV1> for ReadPoint {
+	fn from(point: ReadPointV1) -> Self {
+		// Every unsigned V1 coordinate fits in the signed, wider V2 type
+		ReadPoint {
+			latitude: Coordinate::from_num(point.latitude),
+			longitude: Coordinate::from_num(point.longitude),
+		}
+	}
+}
+
+impl<Moment> From<ReadingV1<Moment>> for ReadingV2<Moment> {
+	fn from(reading: ReadingV1<Moment>) -> Self {
+		// Readings recorded before the device registry carry no signature
+		ReadingV2 {
+			device_id: reading.device_id,
+			reading_type: reading.reading_type,
+			timestamp: reading.timestamp,
+			value: reading.value,
+			signature: DeviceSignature::default(),
+		}
+	}
+}
+
+impl<Moment> From<ShippingEventV1<Moment>> for ShippingEventV2<Moment> {
+	fn from(event: ShippingEventV1<Moment>) -> Self {
+		ShippingEventV2 {
+			id: event.id,
+			event_type: event.event_type,
+			shipment_id: event.shipment_id,
+			location: event.location.map(Into::into),
+			readings: event.readings.into_iter().map(Into::into).collect(),
+			timestamp: event.timestamp,
+		}
+	}
+}
+
+impl<AccountId: Clone, Moment> From<ShipmentV1<AccountId, Moment>> for ShipmentV2<AccountId, Moment> {
+	fn from(shipment: ShipmentV1<AccountId, Moment>) -> Self {
+		// The owner holds the goods until a first custody handoff
+		ShipmentV2 {
+			id: shipment.id,
+			custodian: shipment.owner.clone(),
+			owner: shipment.owner,
+			status: shipment.status,
+			products: shipment.products,
+			registered: shipment.registered,
+			delivered: shipment.delivered,
+			excursions: 0,
+		}
+	}
+}
+
+// Values beyond the signed range, above 32767, are clamped to its maximum
+impl<Moment> From<ReadingV2<Moment>> for Reading<Moment> {
+	fn from(reading: ReadingV2<Moment>) -> Self {
+		Reading {
+			device_id: reading.device_id,
+			reading_type: reading.reading_type,
+			timestamp: reading.timestamp,
+			value: Decimal::saturating_from_num(reading.value),
+			signature: reading.signature,
+		}
+	}
+}
+
+impl<Moment> From<ShippingEventV2<Moment>> for ShippingEvent<Moment> {
+	fn from(event: ShippingEventV2<Moment>) -> Self {
+		ShippingEvent {
+			id: event.id,
+			event_type: event.event_type,
+			shipment_id: event.shipment_id,
+			location: event.location,
+			readings: event.readings.into_iter().map(Into::into).collect(),
+			timestamp: event.timestamp,
+		}
+	}
+}
+
+impl From<ReadingThresholdV2> for ReadingThreshold {
+	fn from(rule: ReadingThresholdV2) -> Self {
+		ReadingThreshold {
+			min: rule.min.map(Decimal::saturating_from_num),
+			max: rule.max.map(Decimal::saturating_from_num),
+		}
+	}
+}
+
+impl<Moment> From<ReadingStatsV2<Moment>> for ReadingStats<Moment> {
+	fn from(stats: ReadingStatsV2<Moment>) -> Self {
+		ReadingStats {
+			count: stats.count,
+			min: Decimal::saturating_from_num(stats.min),
+			max: Decimal::saturating_from_num(stats.max),
+			sum: DecimalSum::saturating_from_num(stats.sum),
+			last_reading: stats.last_reading,
+		}
+	}
+}
+
+impl<AccountId, Moment> From<ShipmentV2<AccountId, Moment>> for Shipment<AccountId, Moment> {
+	fn from(shipment: ShipmentV2<AccountId, Moment>) -> Self {
+		// Shipments registered without a consignee are delivered without acknowledgment
+		Shipment {
+			id: shipment.id,
+			owner: shipment.owner,
+			custodian: shipment.custodian,
+			consignee: None,
+			status: shipment.status,
+			products: shipment.products,
+			registered: shipment.registered,
+			delivered: shipment.delivered,
+			excursions: shipment.excursions,
+		}
+	}
+}
+
+/// Re-encodes shipments (new excursion counter and custodian) and shipping events (signed coordinates,
+/// unsigned readings).
+pub fn migrate_to_v2<T: Trait>() -> Weight {
+	if StorageVersion::get() != Releases::V1 {
+		return T::DbWeight::get().reads(1);
+	}
+
+	// Shipments are written in the V2 layout, that `migrate_to_v4` then translates
+	let shipments = StorageIterator::<ShipmentV1<T::AccountId, T::Moment>>::new(MODULE_PREFIX, b"Shipments")
+		.collect::<Vec<_>>();
+
+	// and so are events, translated by `migrate_to_v9`
+	let events = StorageIterator::<ShippingEventV1<T::Moment>>::new(MODULE_PREFIX, b"AllEvents")
+		.collect::<Vec<_>>();
+
+	let translated = (shipments.len() + events.len()) as Weight;
+	for (key, shipment) in shipments {
+		put_storage_value(MODULE_PREFIX, b"Shipments", &key, ShipmentV2::from(shipment));
+	}
+	for (key, event) in events {
+		put_storage_value(MODULE_PREFIX, b"AllEvents", &key, ShippingEventV2::from(event));
+	}
+	StorageVersion::put(Releases::V2);
+
+	T::DbWeight::get().reads_writes(translated + 1, translated + 1)
+}
+
+/// Splits the `Vec` indexes of shipments and events into paged double maps.
+/// Indexes longer than the new limits are kept whole, they just don't grow anymore.
+pub fn migrate_to_v3<T: Trait>() -> Weight {
+	if StorageVersion::get() != Releases::V2 {
+		return T::DbWeight::get().reads(1);
+	}
+
+	// The V2 maps share their prefix with the double maps replacing them, hence they are
+	// drained completely before anything is written.
+	let (mut reads, mut writes): (Weight, Weight) = (1, 1);
+
+	let shipments_of_org = StorageIterator::<Vec<ShipmentId>>::new(MODULE_PREFIX, b"ShipmentsOfOrganization")
+		.drain()
+		.collect::<Vec<_>>();
+	for (key, shipments) in shipments_of_org {
+		reads += 1;
+		writes += 1;
+		let owner = match key.get(BLAKE2_128_LEN..).map(|mut owner| T::AccountId::decode(&mut owner)) {
+			Some(Ok(owner)) => owner,
+			_ => continue,
+		};
+		for (idx, shipment_id) in shipments.iter().enumerate() {
+			<ShipmentsOfOrganization<T>>::insert(&owner, idx as u32, shipment_id);
+		}
+		ShipmentCountOfOrganization::<T>::insert(&owner, shipments.len() as u32);
+		writes += shipments.len() as Weight + 1;
+	}
+
+	let events_of_shipment = StorageIterator::<Vec<ShippingEventIndex>>::new(MODULE_PREFIX, b"EventsOfShipment")
+		.drain()
+		.collect::<Vec<_>>();
+	for (key, events) in events_of_shipment {
+		reads += 1;
+		writes += 1;
+		let shipment_id = match key.get(BLAKE2_128_LEN..).map(|mut id| ShipmentId::decode(&mut id)) {
+			Some(Ok(shipment_id)) => shipment_id,
+			_ => continue,
+		};
+		for (idx, event_idx) in events.iter().enumerate() {
+			EventsOfShipment::insert(&shipment_id, idx as u32, event_idx);
+		}
+		EventCountOfShipment::insert(&shipment_id, events.len() as u32);
+		writes += events.len() as Weight + 1;
+	}
+	StorageVersion::put(Releases::V3);
+
+	T::DbWeight::get().reads_writes(reads, writes)
+}
+
+/// Re-encodes shipments, that now have an optional consignee
+pub fn migrate_to_v4<T: Trait>() -> Weight {
+	if StorageVersion::get() != Releases::V3 {
+		return T::DbWeight::get().reads(1);
+	}
+
+	let translated = Cell::new(0 as Weight);
+	<Shipments<T> as IterableStorageMap<ShipmentId, Shipment<T::AccountId, T::Moment>>>::translate(
+		|_, shipment: ShipmentV2<T::AccountId, T::Moment>| {
+			translated.set(translated.get() + 1);
+			Some(shipment.into())
+		},
+	);
+	StorageVersion::put(Releases::V4);
+
+	let translated = translated.get();
+	T::DbWeight::get().reads_writes(translated + 1, translated + 1)
+}
+
+/// Drops the members of the V4 release. They joined by themselves and had no roles,
+/// roles are granted through `AdminOrigin` from now on.
+pub fn migrate_to_v5<T: Trait>() -> Weight {
+	if StorageVersion::get() != Releases::V4 {
+		return T::DbWeight::get().reads(1);
+	}
+
+	let removed = Cell::new(0 as Weight);
+	<Members<T> as IterableStorageMap<T::AccountId, Vec<Role>>>::translate(|_, _: ()| {
+		removed.set(removed.get() + 1);
+		None
+	});
+	MemberCount::kill();
+	StorageVersion::put(Releases::V5);
+
+	let removed = removed.get();
+	T::DbWeight::get().reads_writes(removed + 1, removed + 2)
+}
+
+/// Indexes the shipments by the products they carry
+pub fn migrate_to_v6<T: Trait>() -> Weight {
+	if StorageVersion::get() != Releases::V5 {
+		return T::DbWeight::get().reads(1);
+	}
+
+	let (mut shipments, mut indexed): (Weight, Weight) = (0, 0);
+	for (id, shipment) in <Shipments<T> as IterableStorageMap<ShipmentId, Shipment<T::AccountId, T::Moment>>>::iter() {
+		shipments += 1;
+		Module::<T>::index_products(&id, &shipment.products);
+		indexed += shipment.products.len() as Weight;
+	}
+	StorageVersion::put(Releases::V6);
+
+	T::DbWeight::get().reads_writes(shipments + indexed + 1, 2 * indexed + 1)
+}
+
+/// Moves the queue of delivery deadlines into time buckets
+pub fn migrate_to_v7<T: Trait>() -> Weight {
+	if StorageVersion::get() != Releases::V6 {
+		return T::DbWeight::get().reads(1);
+	}
+
+	// The V6 queue is ordered by deadline
+	let queue = take_storage_value::<Vec<(T::Moment, ShipmentId)>>(MODULE_PREFIX, b"DeadlineQueue", &[])
+		.unwrap_or_default();
+	let size = T::DeadlineBucketSize::get();
+	if let Some((first, _)) = queue.first() {
+		<DeadlineCursor<T>>::put((*first / size, 0));
+	}
+	for (deadline, shipment_id) in queue.iter() {
+		let bucket = *deadline / size;
+		let idx = <DeadlineCountOfBucket<T>>::get(bucket);
+		<DeadlineBuckets<T>>::insert(bucket, idx, shipment_id);
+		<DeadlineCountOfBucket<T>>::insert(bucket, idx + 1);
+	}
+	QueuedDeadlines::put(queue.len() as u32);
+	StorageVersion::put(Releases::V7);
+
+	let queued = queue.len() as Weight;
+	T::DbWeight::get().reads_writes(2 * queued + 2, 2 * queued + 4)
+}
+
+/// Indexes the shipments of each organization by ID, so that transfers find them at once
+pub fn migrate_to_v8<T: Trait>() -> Weight {
+	if StorageVersion::get() != Releases::V7 {
+		return T::DbWeight::get().reads(1);
+	}
+
+	let mut indexed: Weight = 0;
+	for (owner, idx, shipment_id) in
+		<ShipmentsOfOrganization<T> as IterableStorageDoubleMap<T::AccountId, u32, ShipmentId>>::iter()
+	{
+		<ShipmentIndexOfOrganization<T>>::insert(&owner, &shipment_id, idx);
+		indexed += 1;
+	}
+	StorageVersion::put(Releases::V8);
+
+	T::DbWeight::get().reads_writes(indexed + 1, indexed + 1)
+}
+
+/// Re-encodes reading values and threshold bounds, that are now signed. Unsigned values
+/// below 32768 keep their encoding, larger ones are clamped to the signed maximum.
+pub fn migrate_to_v9<T: Trait>() -> Weight {
+	if StorageVersion::get() != Releases::V8 {
+		return T::DbWeight::get().reads(1);
+	}
+
+	// `translate` only takes a `Fn`, hence the cell to count translated values
+	let translated = Cell::new(0 as Weight);
+	<AllEvents<T> as IterableStorageMap<ShippingEventIndex, ShippingEvent<T::Moment>>>::translate(
+		|_, event: ShippingEventV2<T::Moment>| {
+			translated.set(translated.get() + 1);
+			Some(event.into())
+		},
+	);
+	<PendingTransfers<T> as IterableStorageMap<
+		ShipmentId,
+		(T::AccountId, T::AccountId, ShippingEvent<T::Moment>),
+	>>::translate(
+		|_, (from, to, event): (T::AccountId, T::AccountId, ShippingEventV2<T::Moment>)| {
+			translated.set(translated.get() + 1);
+			Some((from, to, event.into()))
+		},
+	);
+	<ThresholdRules as IterableStorageDoubleMap<ShipmentId, ReadingType, ReadingThreshold>>::translate(
+		|_, _, rule: ReadingThresholdV2| {
+			translated.set(translated.get() + 1);
+			Some(rule.into())
+		},
+	);
+	<ReadingStatistics<T> as IterableStorageDoubleMap<ShipmentId, ReadingType, ReadingStats<T::Moment>>>::translate(
+		|_, _, stats: ReadingStatsV2<T::Moment>| {
+			translated.set(translated.get() + 1);
+			Some(stats.into())
+		},
+	);
+	StorageVersion::put(Releases::V9);
+
+	let translated = translated.get();
+	T::DbWeight::get().reads_writes(translated + 1, translated + 1)
+}
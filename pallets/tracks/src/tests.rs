@@ -2,20 +2,34 @@
 #![allow(unused_imports)]
 
 use crate::*;
-use frame_support::{assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types};
-use frame_system as system;
-use sp_core::{sr25519, Pair, H256};
+use frame_support::{
+	assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types,
+	storage::unhashed, traits::{Get, IntegrityTest, OnFinalize, OnInitialize, OnRuntimeUpgrade}, Blake2_128Concat, StorageHasher,
+};
+use frame_system::{self as system, EnsureRoot};
+use sp_core::{hashing::twox_128, sr25519, Pair, H256};
 use sp_io::TestExternalities;
 use sp_runtime::{
 	testing::Header,
 	traits::{BlakeTwo256, IdentityLookup},
 	Perbill,
 };
-use fixed::types::U16F16;
+use fixed::types::{U16F16, U48F16};
+use std::cell::RefCell;
+use sp_api::ProvideRuntimeApi;
+use sp_runtime::{generic::BlockId, testing::{Block, ExtrinsicWrapper}};
+use tracks_runtime_api::TracksApi;
 
 use bluefn_util::catalog::ProductId;
+use bluefn_util::geofence::Geofence;
+use bluefn_util::gs1::{Gtin, IdentifierValidator, Sscc};
+use bluefn_util::merkle::{merkle_proof, merkle_root, verify_proof};
 use bluefn_util::shipment::*;
 use bluefn_util::account_key;
+use crate::migration::{
+	ReadPointV1, ReadingStatsV2, ReadingThresholdV2, ReadingV2, ShipmentV1, ShipmentV2, ShippingEventV1,
+	ShippingEventV2,
+};
 
 impl_outer_origin! {
 	pub enum Origin for TestRuntime {}
@@ -66,6 +80,9 @@ impl_outer_event! {
 	pub enum TestEvent for TestRuntime {
 		vec_set<T>,
 		system<T>,
+		delegator<T>,
+		simple_map<T>,
+		basic_token<T>,
 	}
 }
 
@@ -79,13 +96,84 @@ impl timestamp::Trait for TestRuntime {
 	type WeightInfo = ();
 }
 
+impl delegator::Trait for TestRuntime {
+	type Event = TestEvent;
+}
+
+impl simple_map::Trait for TestRuntime {
+	type Event = TestEvent;
+	type ProductIdValidator = Gtin;
+}
+
+impl basic_token::Trait for TestRuntime {
+	type Event = TestEvent;
+}
+
+thread_local! {
+	static REJECT_OUTSIDE_GEOFENCE: RefCell<bool> = RefCell::new(true);
+	static VALIDATE_SSCC: RefCell<bool> = RefCell::new(false);
+}
+
+pub struct RejectDeliveriesOutsideGeofence;
+impl Get<bool> for RejectDeliveriesOutsideGeofence {
+	fn get() -> bool {
+		REJECT_OUTSIDE_GEOFENCE.with(|v| *v.borrow())
+	}
+}
+
+// Most tests use short shipment IDs, SSCCs are only required when enabled
+pub struct TestShipmentIdValidator;
+impl IdentifierValidator for TestShipmentIdValidator {
+	fn is_valid(id: &[u8]) -> bool {
+		!VALIDATE_SSCC.with(|v| *v.borrow()) || Sscc::is_valid(id)
+	}
+}
+
+parameter_types! {
+	pub const MaxClockSkew: u64 = 60_000;
+	pub const RetentionPeriod: u64 = 1000;
+	pub const MaxPrunedEventsPerBlock: u32 = 2;
+	pub const MaxIdentifierLength: u32 = 20;
+	pub const MaxShipmentsPerOrganization: u32 = 3;
+	pub const MaxEventsPerShipment: u32 = 4;
+	pub const MaxEventsPerBatch: u32 = 3;
+	pub const MaxOverdueChecksPerBlock: u32 = 2;
+	pub const DeadlineBucketSize: u64 = 100;
+	pub const AcknowledgmentWindow: u64 = 100;
+	pub const MaxExpiredAcknowledgmentsPerBlock: u32 = 1;
+	pub const MaxRecalledShipmentsPerBlock: u32 = 2;
+}
+
 impl Trait for TestRuntime {
 	type Event = TestEvent;
+	type AdminOrigin = EnsureRoot<Self::AccountId>;
+	type DelegationSource = Delegator;
+	type ProductRegistry = SimpleMap;
+	type Balance = u64;
+	type Escrow = BasicToken;
+	type RejectDeliveriesOutsideGeofence = RejectDeliveriesOutsideGeofence;
+	type MaxClockSkew = MaxClockSkew;
+	type RetentionPeriod = RetentionPeriod;
+	type MaxPrunedEventsPerBlock = MaxPrunedEventsPerBlock;
+	type MaxIdentifierLength = MaxIdentifierLength;
+	type ShipmentIdValidator = TestShipmentIdValidator;
+	type MaxShipmentsPerOrganization = MaxShipmentsPerOrganization;
+	type MaxEventsPerShipment = MaxEventsPerShipment;
+	type MaxEventsPerBatch = MaxEventsPerBatch;
+	type MaxOverdueChecksPerBlock = MaxOverdueChecksPerBlock;
+	type DeadlineBucketSize = DeadlineBucketSize;
+	type AcknowledgmentWindow = AcknowledgmentWindow;
+	type MaxExpiredAcknowledgmentsPerBlock = MaxExpiredAcknowledgmentsPerBlock;
+	type MaxRecalledShipmentsPerBlock = MaxRecalledShipmentsPerBlock;
+	type WeightInfo = ();
 }
 
 pub type Timestamp = timestamp::Module<TestRuntime>;
 pub type System = system::Module<TestRuntime>;
 pub type Tracks = Module<TestRuntime>;
+pub type Delegator = delegator::Module<TestRuntime>;
+pub type SimpleMap = simple_map::Module<TestRuntime>;
+pub type BasicToken = basic_token::Module<TestRuntime>;
 
 pub struct ExtBuilder;
 
@@ -95,23 +183,196 @@ impl ExtBuilder {
 			.build_storage::<TestRuntime>()
 			.unwrap();
 		let mut ext = TestExternalities::from(storage);
+		ext.execute_with(|| {
+			System::set_block_number(1);
+			// Most tests act as any party, roles are tested on their own
+			let roles = vec![Role::Shipper, Role::Carrier, Role::SensorGateway, Role::Auditor];
+			for name in [TEST_SENDER, TEST_ORGANIZATION].iter() {
+				for role in roles.iter() {
+					assert_ok!(Tracks::grant_role(Origin::root(), account_key(name), *role));
+				}
+			}
+			assert_ok!(Tracks::grant_role(Origin::root(), account_key("Bob"), Role::Carrier));
+		});
+		ext
+	}
+
+	pub fn build_with_genesis(config: GenesisConfig<TestRuntime>) -> TestExternalities {
+		let mut storage = system::GenesisConfig::default()
+			.build_storage::<TestRuntime>()
+			.unwrap();
+		config.assimilate_storage(&mut storage).unwrap();
+		let mut ext = TestExternalities::from(storage);
 		ext.execute_with(|| System::set_block_number(1));
 		ext
 	}
 }
 
+// Implements the runtime API the way a runtime would, by forwarding to the module.
+// Calls read the externalities they are made in.
+pub type TestBlock = Block<ExtrinsicWrapper<()>>;
+
+#[derive(Clone)]
+pub struct TestRuntimeApi;
+
+sp_api::mock_impl_runtime_apis! {
+	impl TracksApi<TestBlock, sr25519::Public, u64> for TestRuntimeApi {
+		fn shipments_of_org(
+			owner: sr25519::Public,
+			status: Option<ShipmentStatus>,
+			start: u32,
+			limit: u32,
+		) -> (Vec<Shipment<sr25519::Public, u64>>, Option<u32>) {
+			Tracks::shipments_of_org_by_status(&owner, status, start, limit)
+		}
+
+		fn shipment_timeline(shipment_id: ShipmentId) -> Vec<ShippingEvent<u64>> {
+			Tracks::shipment_timeline(&shipment_id)
+		}
+
+		fn reading_series(
+			shipment_id: ShipmentId,
+			reading_type: ReadingType,
+			from: u64,
+			to: u64,
+		) -> Vec<(u64, Decimal)> {
+			Tracks::reading_series(&shipment_id, reading_type, from, to)
+		}
+	}
+}
+
 const TEST_SENDER: &str = "Alice";
 
 #[test]
-fn add_member_works() {
+fn grant_role_works() {
 	ExtBuilder::build().execute_with(|| {
-		let sender = account_key(TEST_SENDER);
-		assert_ok!(Tracks::add_member(Origin::signed(sender)));
+		let member = account_key("Carol");
+		assert_ok!(Tracks::grant_role(Origin::root(), member, Role::Shipper));
+		assert_ok!(Tracks::grant_role(Origin::root(), member, Role::Auditor));
+
+		let expected_event = TestEvent::vec_set(RawEvent::RoleGranted(member, Role::Auditor));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		assert_eq!(Tracks::roles_of(member), vec![Role::Shipper, Role::Auditor]);
+		assert_eq!(MemberCount::get(), 4);
+		assert!(Tracks::accounts().contains(&member));
+	})
+}
+
+#[test]
+fn grant_role_with_invalid_origin_or_role() {
+	ExtBuilder::build().execute_with(|| {
+		let member = account_key("Carol");
+		assert_noop!(
+			Tracks::grant_role(Origin::signed(account_key(TEST_SENDER)), member, Role::Shipper),
+			dispatch::DispatchError::BadOrigin
+		);
+		assert_noop!(
+			Tracks::grant_role(Origin::root(), account_key(TEST_SENDER), Role::Shipper),
+			Error::<TestRuntime>::AlreadyHasRole
+		);
+	})
+}
+
+#[test]
+fn grant_role_beyond_membership_limit() {
+	ExtBuilder::build().execute_with(|| {
+		for i in MemberCount::get()..MAX_MEMBERS {
+			assert_ok!(Tracks::grant_role(Origin::root(), account_key(&format!("Member{}", i)), Role::Carrier));
+		}
+
+		assert_noop!(
+			Tracks::grant_role(Origin::root(), account_key("Carol"), Role::Carrier),
+			Error::<TestRuntime>::MembershipLimitReached
+		);
+		// Existing members may still get other roles
+		assert_ok!(Tracks::grant_role(Origin::root(), account_key("Bob"), Role::Auditor));
+	})
+}
+
+#[test]
+fn revoke_role_works() {
+	ExtBuilder::build().execute_with(|| {
+		let member = account_key("Bob");
+		assert_ok!(Tracks::grant_role(Origin::root(), member, Role::Auditor));
 
-		let expected_event = TestEvent::vec_set(RawEvent::MemberAdded(sender));
+		assert_ok!(Tracks::revoke_role(Origin::root(), member, Role::Carrier));
+		assert_eq!(Tracks::roles_of(member), vec![Role::Auditor]);
+		assert_eq!(MemberCount::get(), 3);
+
+		assert_ok!(Tracks::revoke_role(Origin::root(), member, Role::Auditor));
+		let expected_event = TestEvent::vec_set(RawEvent::RoleRevoked(member, Role::Auditor));
 		assert!(System::events().iter().any(|a| a.event == expected_event));
+		assert!(!<Members<TestRuntime>>::contains_key(member));
+		assert_eq!(MemberCount::get(), 2);
+
+		assert_noop!(
+			Tracks::revoke_role(Origin::root(), member, Role::Auditor),
+			Error::<TestRuntime>::MissingRole
+		);
+	})
+}
 
-		assert!(<Members<TestRuntime>>::contains_key(sender));
+#[test]
+fn calls_require_role() {
+	ExtBuilder::build().execute_with(|| {
+		let sender = account_key("Bob");
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		// Bob is only a carrier
+		assert_noop!(
+			Tracks::register_shipment(Origin::signed(sender), shipment_id.clone(), sender, vec![], None, None, None),
+			Error::<TestRuntime>::SenderLacksRole
+		);
+		assert_noop!(
+			Tracks::set_threshold_rule(
+				Origin::signed(sender),
+				shipment_id.clone(),
+				ReadingType::Temperature,
+				ReadingThreshold { min: None, max: Some(Decimal::from_num(8)) }
+			),
+			Error::<TestRuntime>::SenderLacksRole
+		);
+		assert_noop!(
+			Tracks::register_device(Origin::signed(sender), TEST_DEVICE_ID.as_bytes().to_owned(), device_pair().public(), sender),
+			Error::<TestRuntime>::SenderLacksRole
+		);
+	})
+}
+
+#[test]
+fn shipping_events_require_role() {
+	ExtBuilder::build().execute_with(|| {
+		let gateway = account_key("Carol");
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::Pending,
+			vec![],
+			42,
+		);
+		assert_ok!(Tracks::grant_role(Origin::root(), gateway, Role::SensorGateway));
+
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(gateway),
+				test_event("0000000001", &shipment_id, ShippingEventType::ShipmentPickup)
+			),
+			Error::<TestRuntime>::SenderLacksRole
+		);
+		assert_ok!(Tracks::record_event(
+			Origin::signed(gateway),
+			test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+		));
+
+		// Auditors may hold shipments
+		assert_ok!(Tracks::grant_role(Origin::root(), gateway, Role::Auditor));
+		assert_ok!(Tracks::record_event(
+			Origin::signed(gateway),
+			test_event("0000000002", &shipment_id, ShippingEventType::ShipmentHold)
+		));
+		assert_eq!(Tracks::shipment_by_id(&shipment_id).map(|s| s.status), Some(ShipmentStatus::Held));
 	})
 }
 
@@ -127,11 +388,14 @@ pub fn store_test_shipment<T: Trait>(
 		id.clone(),
 		Shipment {
 			id,
+			custodian: owner.clone(),
+			consignee: None,
 			owner,
 			status,
 			products,
 			registered,
 			delivered: None,
+			excursions: 0,
 		},
 	);
 }
@@ -149,9 +413,51 @@ pub fn store_test_event<T: Trait>(id: ShippingEventId, shipment_id: ShipmentId)
 	EventCount::put(event_idx);
 	EventIndices::insert(id, event_idx);
 	AllEvents::<T>::insert(event_idx, event);
-	EventsOfShipment::append(shipment_id, event_idx);
+	let idx = EventCountOfShipment::get(&shipment_id);
+	EventsOfShipment::insert(&shipment_id, idx, event_idx);
+	EventCountOfShipment::insert(&shipment_id, idx + 1);
+}
+
+pub fn test_event(id: &str, shipment_id: &[u8], event_type: ShippingEventType) -> ShippingEvent<u64> {
+	ShippingEvent {
+		id: id.as_bytes().to_owned(),
+		event_type,
+		shipment_id: shipment_id.to_vec(),
+		location: None,
+		readings: vec![],
+		timestamp: 42,
+	}
+}
+
+pub fn device_pair() -> sr25519::Pair {
+	sr25519::Pair::from_string(&format!("//{}", TEST_DEVICE_ID), None).unwrap()
+}
+
+pub fn store_test_device<T: Trait>(owner: T::AccountId, revoked: bool) {
+	Devices::<T>::insert(
+		TEST_DEVICE_ID.as_bytes().to_owned(),
+		Device {
+			id: TEST_DEVICE_ID.as_bytes().to_owned(),
+			public_key: device_pair().public(),
+			owner,
+			revoked,
+		},
+	);
 }
 
+pub fn signed_reading(shipment_id: &[u8], reading_type: ReadingType, value: Decimal, timestamp: u64) -> Reading<u64> {
+	let mut reading = Reading {
+		device_id: TEST_DEVICE_ID.as_bytes().to_owned(),
+		reading_type,
+		timestamp,
+		value,
+		signature: Default::default(),
+	};
+	reading.signature = device_pair().sign(&reading.signing_payload(shipment_id));
+	reading
+}
+
+const TEST_DEVICE_ID: &str = "14d453ea4bdf46bc8042";
 const TEST_PRODUCT_ID: &str = "00012345678905";
 const TEST_SHIPMENT_ID: &str = "0001";
 const TEST_ORGANIZATION: &str = "Northwind";
@@ -161,37 +467,113 @@ const LONG_VALUE : &str = "Lorem ipsum dolor sit amet, consectetur adipiscing el
 #[test]
 fn register_shipment_without_products() {
 	ExtBuilder::build().execute_with(|| {
-		let sender = account_key(TEST_SENDER);
 		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
 		let owner = account_key(TEST_ORGANIZATION);
 		let now = 42;
 		Timestamp::set_timestamp(now);
 
 		let result = Tracks::register_shipment(
-			Origin::signed(sender),
+			Origin::signed(owner),
 			id.clone(),
 			owner.clone(),
 			vec![],
+			None,
+			None,
+			None,
 		);
 
 		assert_ok!(result);
 
+		let expected_event = TestEvent::vec_set(RawEvent::ShipmentRegistered(owner, id.clone(), owner));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
 		assert_eq!(
 			Tracks::shipment_by_id(&id),
 			Some(Shipment {
 				id: id.clone(),
 				owner: owner,
+				custodian: owner,
+				consignee: None,
 				status: ShipmentStatus::Pending,
 				products: vec![],
 				registered: now,
-				delivered: None
+				delivered: None,
+				excursions: 0
 			})
 		);
 
-		assert_eq!(<ShipmentsOfOrganization<TestRuntime>>::get(owner), vec![id]);
+		assert_eq!(Tracks::shipments_of_org(&owner, 0, 10), vec![id]);
+	});
+}
+
+
+#[test]
+fn register_shipment_as_delegate() {
+	ExtBuilder::build().execute_with(|| {
+		let sender = account_key(TEST_SENDER);
+		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let owner = account_key(TEST_ORGANIZATION);
+
+		assert_ok!(Delegator::add_delegate(Origin::signed(owner), sender));
+
+		assert_ok!(Tracks::register_shipment(
+			Origin::signed(sender),
+			id.clone(),
+			owner.clone(),
+			vec![],
+			None,
+			None,
+			None,
+		));
+
+		let expected_event = TestEvent::vec_set(RawEvent::DelegationUsed(owner, sender, id.clone()));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		assert_eq!(Tracks::shipments_of_org(&owner, 0, 10), vec![id]);
+	});
+}
+
+#[test]
+fn register_shipment_as_non_delegate() {
+	ExtBuilder::build().execute_with(|| {
+		assert_noop!(
+            Tracks::register_shipment(
+                Origin::signed(account_key(TEST_SENDER)),
+                TEST_SHIPMENT_ID.as_bytes().to_owned(),
+                account_key(TEST_ORGANIZATION),
+                vec!(),
+                None,
+                None,
+                None
+            ),
+            Error::<TestRuntime>::SenderIsNotOwnerOrDelegate
+        );
 	});
 }
 
+#[test]
+fn register_shipment_as_revoked_delegate() {
+	ExtBuilder::build().execute_with(|| {
+		let sender = account_key(TEST_SENDER);
+		let owner = account_key(TEST_ORGANIZATION);
+
+		assert_ok!(Delegator::add_delegate(Origin::signed(owner), sender));
+		assert_ok!(Delegator::remove_delegate(Origin::signed(owner), sender));
+
+		assert_noop!(
+            Tracks::register_shipment(
+                Origin::signed(sender),
+                TEST_SHIPMENT_ID.as_bytes().to_owned(),
+                owner,
+                vec!(),
+                None,
+                None,
+                None
+            ),
+            Error::<TestRuntime>::SenderIsNotOwnerOrDelegate
+        );
+	});
+}
 
 #[test]
 fn register_shipment_with_invalid_sender() {
@@ -201,7 +583,10 @@ fn register_shipment_with_invalid_sender() {
                 Origin::none(),
                 TEST_SHIPMENT_ID.as_bytes().to_owned(),
                 account_key(TEST_ORGANIZATION),
-                vec!()
+                vec!(),
+                None,
+                None,
+                None
             ),
             dispatch::DispatchError::BadOrigin
         );
@@ -216,7 +601,10 @@ fn register_shipment_with_missing_id() {
                 Origin::signed(account_key(TEST_SENDER)),
                 vec!(),
                 account_key(TEST_ORGANIZATION),
-                vec!()
+                vec!(),
+                None,
+                None,
+                None
             ),
             Error::<TestRuntime>::InvalidOrMissingIdentifier
         );
@@ -231,13 +619,31 @@ fn register_shipment_with_long_id() {
                 Origin::signed(account_key(TEST_SENDER)),
                 LONG_VALUE.as_bytes().to_owned(),
                 account_key(TEST_ORGANIZATION),
-                vec!()
+                vec!(),
+                None,
+                None,
+                None
             ),
             Error::<TestRuntime>::InvalidOrMissingIdentifier
         );
 	})
 }
 
+#[test]
+fn register_shipment_with_invalid_sscc() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		VALIDATE_SSCC.with(|v| *v.borrow_mut() = true);
+
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), b"106141412345678908".to_vec(), owner, vec![], None, None, None));
+		// Typo in the serial number
+		assert_noop!(
+			Tracks::register_shipment(Origin::signed(owner), b"106141412345687908".to_vec(), owner, vec![], None, None, None),
+			Error::<TestRuntime>::InvalidShipmentId
+		);
+	})
+}
+
 #[test]
 fn register_shipment_with_existing_id() {
 	ExtBuilder::build().execute_with(|| {
@@ -254,16 +660,87 @@ fn register_shipment_with_existing_id() {
 
 		assert_noop!(
             Tracks::register_shipment(
-                Origin::signed(account_key(TEST_SENDER)),
+                Origin::signed(account_key(TEST_ORGANIZATION)),
                 existing_shipment,
                 account_key(TEST_ORGANIZATION),
-                vec![]
+                vec![],
+                None,
+                None,
+                None
             ),
             Error::<TestRuntime>::ShipmentAlreadyExists
         );
 	})
 }
 
+#[test]
+fn register_shipment_with_registered_products() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let product_id = TEST_PRODUCT_ID.as_bytes().to_owned();
+		assert_ok!(SimpleMap::register_product(Origin::signed(owner), product_id.clone(), owner, None));
+
+		assert_ok!(Tracks::register_shipment(
+			Origin::signed(owner),
+			TEST_SHIPMENT_ID.as_bytes().to_owned(),
+			owner,
+			vec![product_id.clone()],
+			None,
+			None,
+			None
+		));
+		assert_eq!(
+			Tracks::shipment_by_id(TEST_SHIPMENT_ID.as_bytes().to_owned()).map(|s| s.products),
+			Some(vec![product_id.clone()])
+		);
+		assert_eq!(Tracks::shipment_count_of_product(&product_id), 1);
+		assert_eq!(Tracks::shipment_of_product_by_idx(&product_id, 0), Some(TEST_SHIPMENT_ID.as_bytes().to_owned()));
+	})
+}
+
+#[test]
+fn register_shipment_with_unknown_product() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+
+		assert_noop!(
+			Tracks::register_shipment(
+				Origin::signed(owner),
+				TEST_SHIPMENT_ID.as_bytes().to_owned(),
+				owner,
+				vec![TEST_PRODUCT_ID.as_bytes().to_owned()],
+				None,
+				None,
+				None
+			),
+			Error::<TestRuntime>::ProductIsUnknown
+		);
+	})
+}
+
+#[test]
+fn register_shipment_with_deregistered_product() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let product_id = TEST_PRODUCT_ID.as_bytes().to_owned();
+		assert_ok!(SimpleMap::register_product(Origin::signed(owner), product_id.clone(), owner, None));
+		assert_ok!(SimpleMap::deregister_product(Origin::signed(owner), product_id.clone()));
+
+		assert_noop!(
+			Tracks::register_shipment(
+				Origin::signed(owner),
+				TEST_SHIPMENT_ID.as_bytes().to_owned(),
+				owner,
+				vec![product_id],
+				None,
+				None,
+				None
+			),
+			Error::<TestRuntime>::ProductIsUnknown
+		);
+	})
+}
+
 #[test]
 fn register_shipment_with_too_many_products() {
 	ExtBuilder::build().execute_with(|| {
@@ -284,7 +761,10 @@ fn register_shipment_with_too_many_products() {
                     b"00012345600009".to_vec(),
                     b"00012345600010".to_vec(),
                     b"00012345600011".to_vec(),
-                ]
+                ],
+                None,
+                None,
+                None
             ),
             Error::<TestRuntime>::ShipmentHasTooManyProducts
         );
@@ -493,7 +973,7 @@ fn record_event_for_shipment_pickup() {
 		assert_eq!(EventCount::get(), 1);
 		assert_eq!(EventIndices::get(event_id), Some(1));
 		assert_eq!(AllEvents::<TestRuntime>::get(1), Some(event));
-		assert_eq!(EventsOfShipment::get(&shipment_id), vec![1]);
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1]);
 
 		// Shipment's status should be updated to 'InTransit'
 		assert_eq!(
@@ -501,10 +981,13 @@ fn record_event_for_shipment_pickup() {
 			Some(Shipment {
 				id: shipment_id.clone(),
 				owner: owner,
+				custodian: owner,
+				consignee: None,
 				status: ShipmentStatus::InTransit,
 				products: vec![TEST_PRODUCT_ID.as_bytes().to_owned()],
 				registered: now,
-				delivered: None
+				delivered: None,
+				excursions: 0
 			})
 		);
 	})
@@ -546,7 +1029,7 @@ fn record_event_for_shipment_delivery() {
 		assert_eq!(EventCount::get(), 1);
 		assert_eq!(EventIndices::get(event_id), Some(1));
 		assert_eq!(AllEvents::<TestRuntime>::get(1), Some(event));
-		assert_eq!(EventsOfShipment::get(&shipment_id), vec![1]);
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1]);
 
 		// Shipment's status should be updated to 'InTransit'
 		// and delivered timestamp updated
@@ -555,10 +1038,13 @@ fn record_event_for_shipment_delivery() {
 			Some(Shipment {
 				id: shipment_id.clone(),
 				owner: owner,
+				custodian: owner,
+				consignee: None,
 				status: ShipmentStatus::Delivered,
 				products: vec![TEST_PRODUCT_ID.as_bytes().to_owned()],
 				registered: now,
-				delivered: Some(now)
+				delivered: Some(now),
+				excursions: 0
 			})
 		);
 	})
@@ -585,6 +1071,7 @@ fn record_event_for_sensor_reading() {
 			hex::decode("88356e4576444cae8c78").unwrap(),
 			shipment_id.clone(),
 		);
+		store_test_device::<TestRuntime>(owner, false);
 
 		// Dispatchable call succeeds
 		let event = ShippingEvent {
@@ -592,15 +1079,15 @@ fn record_event_for_sensor_reading() {
 			event_type: ShippingEventType::SensorReading,
 			shipment_id: shipment_id.clone(),
 			location: Some(ReadPoint {
-				latitude: U16F16::from_num(52.4941126),
-				longitude: U16F16::from_num(13.4355606),
+				latitude: Coordinate::from_num(52.4941126),
+				longitude: Coordinate::from_num(13.4355606),
 			}),
-			readings: vec![Reading {
-				device_id: "14d453ea4bdf46bc8042".as_bytes().to_owned(),
-				reading_type: ReadingType::Temperature,
-				value: U16F16::from_num(20.123),
-				timestamp: now,
-			}],
+			readings: vec![signed_reading(
+				&shipment_id,
+				ReadingType::Temperature,
+				Decimal::from_num(20.123),
+				now,
+			)],
 			timestamp: now,
 		};
 		assert_ok!(Tracks::record_event(
@@ -612,7 +1099,7 @@ fn record_event_for_sensor_reading() {
 		assert_eq!(EventCount::get(), 2);
 		assert_eq!(EventIndices::get(event_id), Some(2));
 		assert_eq!(AllEvents::<TestRuntime>::get(2), Some(event));
-		assert_eq!(EventsOfShipment::get(&shipment_id), vec![1, 2]);
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1, 2]);
 
 		// Shipment's status should still be 'InTransit'
 		assert_eq!(
@@ -620,10 +1107,13 @@ fn record_event_for_sensor_reading() {
 			Some(Shipment {
 				id: shipment_id.clone(),
 				owner: owner,
+				custodian: owner,
+				consignee: None,
 				status: ShipmentStatus::InTransit,
 				products: vec![TEST_PRODUCT_ID.as_bytes().to_owned()],
 				registered: now,
-				delivered: None
+				delivered: None,
+				excursions: 0
 			})
 		);
 	})
@@ -696,3 +1186,2553 @@ fn record_event_for_intransit_shipment() {
         );
 	})
 }
+
+#[test]
+fn record_event_for_shipment_cancellation() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let event_id = hex::decode(TEST_SHIPPING_EVENT_ID).unwrap();
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let now = 42;
+
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			owner,
+			ShipmentStatus::Pending,
+			vec![TEST_PRODUCT_ID.as_bytes().to_owned()],
+			now,
+		);
+
+		assert_ok!(Tracks::record_event(
+            Origin::signed(account_key(TEST_SENDER)),
+            ShippingEvent {
+                id: event_id,
+                event_type: ShippingEventType::ShipmentCancellation,
+                shipment_id: shipment_id.clone(),
+                location: None,
+                readings: vec![],
+                timestamp: now,
+            }
+        ));
+
+		let expected_event = TestEvent::vec_set(RawEvent::ShipmentStatusUpdated(
+			shipment_id.clone(),
+			ShipmentStatus::Cancelled,
+		));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+		assert_eq!(
+			Tracks::shipment_by_id(&shipment_id).map(|s| s.status),
+			Some(ShipmentStatus::Cancelled)
+		);
+	})
+}
+
+#[test]
+fn record_event_for_held_shipment_pickup() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let now = 42;
+
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			owner,
+			ShipmentStatus::InTransit,
+			vec![],
+			now,
+		);
+
+		for (id, event_type) in vec![
+			(b"0000000001".to_vec(), ShippingEventType::ShipmentHold),
+			(b"0000000002".to_vec(), ShippingEventType::ShipmentPickup),
+			(b"0000000003".to_vec(), ShippingEventType::ShipmentDamage),
+		] {
+			assert_ok!(Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				ShippingEvent {
+					id,
+					event_type,
+					shipment_id: shipment_id.clone(),
+					location: None,
+					readings: vec![],
+					timestamp: now,
+				}
+			));
+		}
+
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1, 2, 3]);
+		assert_eq!(
+			Tracks::shipment_by_id(&shipment_id).map(|s| s.status),
+			Some(ShipmentStatus::Damaged)
+		);
+	})
+}
+
+#[test]
+fn record_event_for_cancelled_shipment() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let event_id = hex::decode(TEST_SHIPPING_EVENT_ID).unwrap();
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let now = 42;
+
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			owner,
+			ShipmentStatus::Cancelled,
+			vec![TEST_PRODUCT_ID.as_bytes().to_owned()],
+			now,
+		);
+
+		assert_noop!(
+            Tracks::record_event(
+                Origin::signed(account_key(TEST_SENDER)),
+                ShippingEvent {
+                    id: event_id,
+                    event_type: ShippingEventType::ShipmentPickup,
+                    shipment_id: shipment_id.clone(),
+                    location: None,
+                    readings: vec![],
+                    timestamp: now,
+                }
+            ),
+            Error::<TestRuntime>::ShipmentStatusTransitionNotAllowed
+        );
+	})
+}
+
+#[test]
+fn record_event_for_lost_pending_shipment() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let event_id = hex::decode(TEST_SHIPPING_EVENT_ID).unwrap();
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let now = 42;
+
+		// A shipment can only get lost once a carrier picked it up
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			owner,
+			ShipmentStatus::Pending,
+			vec![],
+			now,
+		);
+
+		assert_noop!(
+            Tracks::record_event(
+                Origin::signed(account_key(TEST_SENDER)),
+                ShippingEvent {
+                    id: event_id,
+                    event_type: ShippingEventType::ShipmentLoss,
+                    shipment_id: shipment_id.clone(),
+                    location: None,
+                    readings: vec![],
+                    timestamp: now,
+                }
+            ),
+            Error::<TestRuntime>::ShipmentStatusTransitionNotAllowed
+        );
+	})
+}
+
+#[test]
+fn shipments_of_org_by_status_pages() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		for id in vec!["0001", "0002", "0003"] {
+			assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.as_bytes().to_owned(), owner, vec![], None, None, None));
+		}
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			test_event("0000000001", b"0002", ShippingEventType::ShipmentPickup)
+		));
+
+		let (shipments, next) = Tracks::shipments_of_org_by_status(&owner, Some(ShipmentStatus::Pending), 0, 1);
+		assert_eq!(shipments.iter().map(|s| s.id.clone()).collect::<Vec<_>>(), vec![b"0001".to_vec()]);
+		assert_eq!(next, Some(1));
+
+		let (shipments, next) = Tracks::shipments_of_org_by_status(&owner, Some(ShipmentStatus::Pending), 1, 1);
+		assert_eq!(shipments.iter().map(|s| s.id.clone()).collect::<Vec<_>>(), vec![b"0003".to_vec()]);
+		assert_eq!(next, None);
+
+		let (shipments, next) = Tracks::shipments_of_org_by_status(&owner, None, 0, 10);
+		assert_eq!(shipments.len(), 3);
+		assert_eq!(shipments[1].status, ShipmentStatus::InTransit);
+		assert_eq!(next, None);
+	})
+}
+
+#[test]
+fn shipment_timeline_and_reading_series() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+		store_test_device::<TestRuntime>(owner, false);
+		let events = vec![
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.5), 100)],
+				timestamp: 100,
+				..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+			},
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(5.0), 200)],
+				timestamp: 200,
+				..test_event("0000000002", &shipment_id, ShippingEventType::SensorReading)
+			},
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(5.5), 300)],
+				timestamp: 300,
+				..test_event("0000000003", &shipment_id, ShippingEventType::SensorReading)
+			},
+		];
+		for event in events.iter() {
+			assert_ok!(Tracks::record_event(Origin::signed(owner), event.clone()));
+		}
+
+		assert_eq!(Tracks::shipment_timeline(&shipment_id), events);
+		assert_eq!(
+			Tracks::reading_series(&shipment_id, ReadingType::Temperature, 150, 300),
+			vec![(200, Decimal::from_num(5.0)), (300, Decimal::from_num(5.5))]
+		);
+		assert_eq!(Tracks::reading_series(&shipment_id, ReadingType::Humidity, 0, 300), vec![]);
+	})
+}
+
+#[test]
+fn runtime_api_queries_shipments() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let at = BlockId::Number(0);
+
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), shipment_id.clone(), owner, vec![], None, None, None));
+		store_test_device::<TestRuntime>(owner, false);
+		let events = vec![
+			test_event("0000000001", &shipment_id, ShippingEventType::ShipmentPickup),
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(5.0), 200)],
+				timestamp: 200,
+				..test_event("0000000002", &shipment_id, ShippingEventType::SensorReading)
+			},
+		];
+		for event in events.iter() {
+			assert_ok!(Tracks::record_event(Origin::signed(owner), event.clone()));
+		}
+
+		let api = TestRuntimeApi.runtime_api();
+		let (shipments, next) = api
+			.shipments_of_org(&at, owner, Some(ShipmentStatus::InTransit), 0, 10)
+			.expect("mock API calls succeed; qed");
+		assert_eq!(shipments.iter().map(|s| s.id.clone()).collect::<Vec<_>>(), vec![shipment_id.clone()]);
+		assert_eq!(next, None);
+		assert_eq!(api.shipment_timeline(&at, shipment_id.clone()).expect("mock API calls succeed; qed"), events);
+		assert_eq!(
+			api.reading_series(&at, shipment_id, ReadingType::Temperature, 0, 300)
+				.expect("mock API calls succeed; qed"),
+			vec![(200, Decimal::from_num(5.0))]
+		);
+	})
+}
+
+#[test]
+fn record_event_updates_reading_stats() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+		store_test_device::<TestRuntime>(owner, false);
+		assert_eq!(Tracks::reading_stats(&shipment_id, ReadingType::Temperature), None);
+
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(5.0), 100)],
+				timestamp: 100,
+				..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+			}
+		));
+		assert_eq!(
+			Tracks::reading_stats(&shipment_id, ReadingType::Temperature),
+			Some(ReadingStats {
+				count: 1,
+				min: Decimal::from_num(5.0),
+				max: Decimal::from_num(5.0),
+				sum: DecimalSum::from_num(5.0),
+				last_reading: 100,
+			})
+		);
+
+		assert_ok!(Tracks::record_events(
+			Origin::signed(owner),
+			vec![
+				ShippingEvent {
+					readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(2.5), 200)],
+					timestamp: 200,
+					..test_event("0000000002", &shipment_id, ShippingEventType::SensorReading)
+				},
+				ShippingEvent {
+					readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(7.5), 300)],
+					timestamp: 300,
+					..test_event("0000000003", &shipment_id, ShippingEventType::SensorReading)
+				},
+				ShippingEvent {
+					readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(5.0), 400)],
+					timestamp: 400,
+					..test_event("0000000004", &shipment_id, ShippingEventType::SensorReading)
+				},
+			],
+			true
+		));
+		let stats = Tracks::reading_stats(&shipment_id, ReadingType::Temperature).unwrap();
+		assert_eq!(stats.count, 4);
+		assert_eq!(stats.min, Decimal::from_num(2.5));
+		assert_eq!(stats.max, Decimal::from_num(7.5));
+		assert_eq!(stats.sum, DecimalSum::from_num(20));
+		assert_eq!(stats.mean(), Decimal::from_num(5.0));
+		assert_eq!(stats.last_reading, 400);
+		assert_eq!(Tracks::reading_stats(&shipment_id, ReadingType::Humidity), None);
+	})
+}
+
+#[test]
+fn record_event_dated_before_registration() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+		store_test_device::<TestRuntime>(owner, false);
+
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(owner),
+				ShippingEvent {
+					readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.0), 41)],
+					timestamp: 41,
+					..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+				}
+			),
+			Error::<TestRuntime>::ShippingEventBeforeRegistration
+		);
+
+		let event = ShippingEvent {
+			readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.0), 41)],
+			..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+		};
+		assert_noop!(
+			Tracks::record_event(Origin::signed(owner), event),
+			Error::<TestRuntime>::ReadingBeforeRegistration
+		);
+	})
+}
+
+#[test]
+fn record_event_dated_in_future() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+		store_test_device::<TestRuntime>(owner, false);
+		Timestamp::set_timestamp(100_000);
+
+		// Within the clock skew tolerance
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.0), 160_000)],
+				timestamp: 160_000,
+				..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+			}
+		));
+
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(owner),
+				ShippingEvent {
+					readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.0), 160_001)],
+					timestamp: 160_001,
+					..test_event("0000000002", &shipment_id, ShippingEventType::SensorReading)
+				}
+			),
+			Error::<TestRuntime>::ShippingEventInFuture
+		);
+
+		let event = ShippingEvent {
+			readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.0), 160_001)],
+			timestamp: 160_000,
+			..test_event("0000000002", &shipment_id, ShippingEventType::SensorReading)
+		};
+		assert_noop!(
+			Tracks::record_event(Origin::signed(owner), event),
+			Error::<TestRuntime>::ReadingAfterShippingEvent
+		);
+	})
+}
+
+#[test]
+fn record_event_out_of_order() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+		store_test_device::<TestRuntime>(owner, false);
+
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.0), 200)],
+				timestamp: 200,
+				..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+			}
+		));
+		// Events dated alike are fine
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.0), 200)],
+				timestamp: 200,
+				..test_event("0000000002", &shipment_id, ShippingEventType::SensorReading)
+			}
+		));
+
+		// A backdated delivery
+		let mut event = test_event("0000000003", &shipment_id, ShippingEventType::ShipmentDelivery);
+		event.timestamp = 100;
+		assert_noop!(
+			Tracks::record_event(Origin::signed(owner), event),
+			Error::<TestRuntime>::ShippingEventOutOfOrder
+		);
+	})
+}
+
+#[test]
+fn set_threshold_rule_works() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let rule = ReadingThreshold {
+			min: Some(Decimal::from_num(2)),
+			max: Some(Decimal::from_num(8)),
+		};
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::Pending, vec![], 42);
+
+		assert_ok!(Tracks::set_threshold_rule(
+			Origin::signed(owner),
+			shipment_id.clone(),
+			ReadingType::Temperature,
+			rule.clone()
+		));
+
+		assert_eq!(Tracks::threshold_rule(&shipment_id, ReadingType::Temperature), Some(rule));
+
+		assert_ok!(Tracks::remove_threshold_rule(
+			Origin::signed(owner),
+			shipment_id.clone(),
+			ReadingType::Temperature
+		));
+		assert_eq!(Tracks::threshold_rule(&shipment_id, ReadingType::Temperature), None);
+	})
+}
+
+#[test]
+fn set_threshold_rule_with_invalid_rule() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::Pending, vec![], 42);
+
+		assert_noop!(
+			Tracks::set_threshold_rule(
+				Origin::signed(owner),
+				shipment_id.clone(),
+				ReadingType::Temperature,
+				ReadingThreshold {
+					min: Some(Decimal::from_num(8)),
+					max: Some(Decimal::from_num(2)),
+				}
+			),
+			Error::<TestRuntime>::InvalidThresholdRule
+		);
+		assert_noop!(
+			Tracks::set_threshold_rule(
+				Origin::signed(owner),
+				shipment_id,
+				ReadingType::Shock,
+				ReadingThreshold { min: None, max: None }
+			),
+			Error::<TestRuntime>::InvalidThresholdRule
+		);
+	})
+}
+
+#[test]
+fn set_threshold_rule_as_non_delegate() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::Pending, vec![], 42);
+
+		assert_noop!(
+			Tracks::set_threshold_rule(
+				Origin::signed(account_key(TEST_SENDER)),
+				shipment_id,
+				ReadingType::Humidity,
+				ReadingThreshold { min: None, max: Some(Decimal::from_num(80)) }
+			),
+			Error::<TestRuntime>::SenderIsNotOwnerOrDelegate
+		);
+	})
+}
+
+#[test]
+fn record_event_with_threshold_excursion() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let now = 42;
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], now);
+		store_test_device::<TestRuntime>(owner, false);
+		assert_ok!(Tracks::set_threshold_rule(
+			Origin::signed(owner),
+			shipment_id.clone(),
+			ReadingType::Temperature,
+			ReadingThreshold {
+				min: Some(Decimal::from_num(2)),
+				max: Some(Decimal::from_num(8)),
+			}
+		));
+
+		// Within range
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.5), now)],
+				timestamp: now,
+				..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+			}
+		));
+		assert_eq!(Tracks::shipment_by_id(&shipment_id).map(|s| s.excursions), Some(0));
+
+		// Too warm
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(12.0), now)],
+				timestamp: now,
+				..test_event("0000000002", &shipment_id, ShippingEventType::SensorReading)
+			}
+		));
+
+		let expected_event = TestEvent::vec_set(RawEvent::ThresholdExcursion(
+			shipment_id.clone(),
+			b"0000000002".to_vec(),
+			ReadingType::Temperature,
+			Decimal::from_num(12.0),
+		));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		let shipment = Tracks::shipment_by_id(&shipment_id).unwrap();
+		assert_eq!(shipment.excursions, 1);
+		assert_eq!(shipment.status, ShipmentStatus::InTransit);
+	})
+}
+
+#[test]
+fn record_event_with_sub_zero_threshold() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let now = 42;
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], now);
+		store_test_device::<TestRuntime>(owner, false);
+		// Frozen goods, kept below -18 °C
+		assert_ok!(Tracks::set_threshold_rule(
+			Origin::signed(owner),
+			shipment_id.clone(),
+			ReadingType::Temperature,
+			ReadingThreshold { min: Some(Decimal::from_num(-30)), max: Some(Decimal::from_num(-18)) }
+		));
+
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(-21.5), now)],
+				timestamp: now,
+				..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+			}
+		));
+		assert_eq!(Tracks::shipment_by_id(&shipment_id).map(|s| s.excursions), Some(0));
+
+		// Thawing
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			ShippingEvent {
+				readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(-4.0), now)],
+				timestamp: now,
+				..test_event("0000000002", &shipment_id, ShippingEventType::SensorReading)
+			}
+		));
+		let expected_event = TestEvent::vec_set(RawEvent::ThresholdExcursion(
+			shipment_id.clone(),
+			b"0000000002".to_vec(),
+			ReadingType::Temperature,
+			Decimal::from_num(-4.0),
+		));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+		assert_eq!(Tracks::shipment_by_id(&shipment_id).map(|s| s.excursions), Some(1));
+		assert_eq!(
+			Tracks::reading_stats(&shipment_id, ReadingType::Temperature).map(|stats| stats.mean()),
+			Some(Decimal::from_num(-12.75))
+		);
+	})
+}
+
+#[test]
+fn record_event_with_signed_location() {
+	ExtBuilder::build().execute_with(|| {
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::InTransit,
+			vec![],
+			42,
+		);
+
+		// Buenos Aires
+		let event = ShippingEvent {
+			location: Some(ReadPoint { latitude: Coordinate::from_num(-34.6037), longitude: Coordinate::from_num(-58.3816) }),
+			..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+		};
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			event.clone()
+		));
+		assert_eq!(AllEvents::<TestRuntime>::get(1), Some(event));
+
+		// Along the antimeridian
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			ShippingEvent {
+				location: Some(ReadPoint { latitude: Coordinate::from_num(90.0), longitude: Coordinate::from_num(-180.0) }),
+				..test_event("0000000002", &shipment_id, ShippingEventType::SensorReading)
+			}
+		));
+	})
+}
+
+#[test]
+fn record_event_with_out_of_range_location() {
+	ExtBuilder::build().execute_with(|| {
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::InTransit,
+			vec![],
+			42,
+		);
+
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				ShippingEvent {
+					location: Some(ReadPoint { latitude: Coordinate::from_num(-90.5), longitude: Coordinate::from_num(13.4) }),
+					..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+				}
+			),
+			Error::<TestRuntime>::LatitudeOutOfRange
+		);
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				ShippingEvent {
+					location: Some(ReadPoint { latitude: Coordinate::from_num(52.4), longitude: Coordinate::from_num(180.001) }),
+					..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+				}
+			),
+			Error::<TestRuntime>::LongitudeOutOfRange
+		);
+	})
+}
+
+#[test]
+fn runtime_upgrade_migrates_to_v2() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let event_id = hex::decode(TEST_SHIPPING_EVENT_ID).unwrap();
+
+		// Values as written by the V1 release
+		unhashed::put(
+			&Shipments::<TestRuntime>::hashed_key_for(&shipment_id),
+			&ShipmentV1 {
+				id: shipment_id.clone(),
+				owner,
+				status: ShipmentStatus::InTransit,
+				products: vec![],
+				registered: 42u64,
+				delivered: None,
+			},
+		);
+		unhashed::put(
+			&AllEvents::<TestRuntime>::hashed_key_for(1),
+			&ShippingEventV1 {
+				id: event_id.clone(),
+				event_type: ShippingEventType::SensorReading,
+				shipment_id: shipment_id.clone(),
+				location: Some(ReadPointV1 {
+					latitude: U16F16::from_num(52.4941126),
+					longitude: U16F16::from_num(13.4355606),
+				}),
+				readings: vec![],
+				timestamp: 42u64,
+			},
+		);
+
+		Tracks::on_runtime_upgrade();
+
+		assert_eq!(StorageVersion::get(), Releases::V9);
+		assert_eq!(Tracks::shipment_by_id(&shipment_id).map(|s| s.excursions), Some(0));
+		assert_eq!(
+			Tracks::event_by_idx(1).and_then(|e| e.location),
+			Some(ReadPoint {
+				latitude: Coordinate::from_num(U16F16::from_num(52.4941126)),
+				longitude: Coordinate::from_num(U16F16::from_num(13.4355606)),
+			})
+		);
+	})
+}
+
+#[test]
+fn runtime_upgrade_migrates_to_v3() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		StorageVersion::put(Releases::V2);
+
+		// Indexes as written by the V2 release
+		let prefix = [twox_128(b"VecMap"), twox_128(b"ShipmentsOfOrganization")].concat();
+		unhashed::put(&[&prefix[..], &Blake2_128Concat::hash(&owner.encode())].concat(), &vec![shipment_id.clone()]);
+		let prefix = [twox_128(b"VecMap"), twox_128(b"EventsOfShipment")].concat();
+		unhashed::put(&[&prefix[..], &Blake2_128Concat::hash(&shipment_id.encode())].concat(), &vec![1u64, 2u64]);
+
+		Tracks::on_runtime_upgrade();
+
+		assert_eq!(StorageVersion::get(), Releases::V9);
+		assert_eq!(Tracks::shipment_count_of_org(&owner), 1);
+		assert_eq!(Tracks::shipments_of_org(&owner, 0, 10), vec![shipment_id.clone()]);
+		assert_eq!(Tracks::event_count_of_shipment(&shipment_id), 2);
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1, 2]);
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 1, 10), vec![2]);
+	})
+}
+
+#[test]
+fn runtime_upgrade_migrates_to_v4() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let custodian = account_key(TEST_SENDER);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		StorageVersion::put(Releases::V3);
+
+		// Values as written by the V3 release
+		unhashed::put(
+			&Shipments::<TestRuntime>::hashed_key_for(&shipment_id),
+			&ShipmentV2 {
+				id: shipment_id.clone(),
+				owner,
+				custodian,
+				status: ShipmentStatus::InTransit,
+				products: vec![],
+				registered: 42u64,
+				delivered: None,
+				excursions: 2,
+			},
+		);
+
+		Tracks::on_runtime_upgrade();
+
+		assert_eq!(StorageVersion::get(), Releases::V9);
+		assert_eq!(
+			Tracks::shipment_by_id(&shipment_id),
+			Some(Shipment {
+				id: shipment_id.clone(),
+				owner,
+				custodian,
+				consignee: None,
+				status: ShipmentStatus::InTransit,
+				products: vec![],
+				registered: 42,
+				delivered: None,
+				excursions: 2,
+			})
+		);
+	})
+}
+
+#[test]
+fn runtime_upgrade_migrates_to_v5() {
+	ExtBuilder::build().execute_with(|| {
+		let member = account_key("Carol");
+		StorageVersion::put(Releases::V4);
+
+		// Members as written by the V4 release
+		unhashed::put_raw(&Members::<TestRuntime>::hashed_key_for(&member), &[]);
+		MemberCount::put(1);
+
+		Tracks::on_runtime_upgrade();
+
+		assert_eq!(StorageVersion::get(), Releases::V9);
+		assert!(!<Members<TestRuntime>>::contains_key(&member));
+		assert_eq!(MemberCount::get(), 0);
+	})
+}
+
+#[test]
+fn runtime_upgrade_migrates_to_v6() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let product_id = TEST_PRODUCT_ID.as_bytes().to_owned();
+		StorageVersion::put(Releases::V5);
+
+		// Shipments registered by the V5 release, which had no product index
+		store_test_shipment::<TestRuntime>(b"0001".to_vec(), owner, ShipmentStatus::InTransit, vec![product_id.clone()], 42);
+		store_test_shipment::<TestRuntime>(b"0002".to_vec(), owner, ShipmentStatus::Delivered, vec![product_id.clone()], 42);
+
+		Tracks::on_runtime_upgrade();
+
+		assert_eq!(StorageVersion::get(), Releases::V9);
+		// Closed shipments are indexed too
+		assert_eq!(Tracks::shipment_count_of_product(&product_id), 2);
+		assert!(Tracks::shipment_of_product_by_idx(&product_id, 0).is_some());
+		assert!(Tracks::shipment_of_product_by_idx(&product_id, 1).is_some());
+		assert_eq!(
+			Tracks::product_locations(&product_id, 0, 10),
+			(vec![(b"0001".to_vec(), ShipmentStatus::InTransit, owner)], None)
+		);
+	})
+}
+
+#[test]
+fn runtime_upgrade_migrates_to_v7() {
+	ExtBuilder::build().execute_with(|| {
+		StorageVersion::put(Releases::V6);
+
+		// Deadlines queued by the V6 release
+		let key = [twox_128(b"VecMap"), twox_128(b"DeadlineQueue")].concat();
+		unhashed::put(&key, &vec![(150u64, b"0001".to_vec()), (180u64, b"0002".to_vec()), (420u64, b"0003".to_vec())]);
+
+		Tracks::on_runtime_upgrade();
+
+		assert_eq!(StorageVersion::get(), Releases::V9);
+		assert!(!unhashed::exists(&key));
+		assert_eq!(Tracks::deadline_by_idx(1, 1), Some(b"0002".to_vec()));
+		assert_eq!(Tracks::deadline_by_idx(4, 0), Some(b"0003".to_vec()));
+		assert_eq!(Tracks::deadline_count_of_bucket(1), 2);
+		assert_eq!(Tracks::queued_deadlines(), 3);
+		assert_eq!(Tracks::deadline_cursor(), (1, 0));
+	})
+}
+
+#[test]
+fn runtime_upgrade_migrates_to_v8() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		StorageVersion::put(Releases::V7);
+
+		// Shipments indexed by the V7 release, which had no reverse index
+		ShipmentsOfOrganization::<TestRuntime>::insert(&owner, 0, b"0001".to_vec());
+		ShipmentsOfOrganization::<TestRuntime>::insert(&owner, 1, b"0002".to_vec());
+		ShipmentCountOfOrganization::<TestRuntime>::insert(&owner, 2);
+
+		Tracks::on_runtime_upgrade();
+
+		assert_eq!(StorageVersion::get(), Releases::V9);
+		assert_eq!(Tracks::shipment_idx_of_org(&owner, b"0001".to_vec()), Some(0));
+		assert_eq!(Tracks::shipment_idx_of_org(&owner, b"0002".to_vec()), Some(1));
+	})
+}
+
+#[test]
+fn runtime_upgrade_migrates_to_v9() {
+	ExtBuilder::build().execute_with(|| {
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let reading = signed_reading(&shipment_id, ReadingType::Pressure, Decimal::from_num(4.5), 42);
+		StorageVersion::put(Releases::V8);
+
+		// Values as written by the V8 release, with unsigned readings and bounds
+		let unsigned_reading = |value: f64| ReadingV2 {
+			device_id: reading.device_id.clone(),
+			reading_type: ReadingType::Pressure,
+			timestamp: 42u64,
+			value: U16F16::from_num(value),
+			signature: reading.signature.clone(),
+		};
+		unhashed::put(
+			&AllEvents::<TestRuntime>::hashed_key_for(1),
+			&ShippingEventV2 {
+				id: b"0000000001".to_vec(),
+				event_type: ShippingEventType::SensorReading,
+				shipment_id: shipment_id.clone(),
+				location: None,
+				readings: vec![unsigned_reading(4.5), unsigned_reading(40000.0)],
+				timestamp: 42u64,
+			},
+		);
+		unhashed::put(
+			&ThresholdRules::hashed_key_for(&shipment_id, ReadingType::Pressure),
+			&ReadingThresholdV2 { min: None, max: Some(U16F16::from_num(50000)) },
+		);
+		unhashed::put(
+			&ReadingStatistics::<TestRuntime>::hashed_key_for(&shipment_id, ReadingType::Pressure),
+			&ReadingStatsV2 {
+				count: 2,
+				min: U16F16::from_num(4.5),
+				max: U16F16::from_num(40000),
+				sum: U48F16::from_num(40004.5),
+				last_reading: 42u64,
+			},
+		);
+
+		Tracks::on_runtime_upgrade();
+
+		assert_eq!(StorageVersion::get(), Releases::V9);
+		// Values beyond the signed range are clamped
+		assert_eq!(
+			Tracks::event_by_idx(1).map(|e| e.readings.into_iter().map(|r| r.value).collect::<Vec<_>>()),
+			Some(vec![Decimal::from_num(4.5), Decimal::max_value()])
+		);
+		assert_eq!(Tracks::event_by_idx(1).map(|e| e.readings[0].clone()), Some(reading));
+		assert_eq!(
+			Tracks::threshold_rule(&shipment_id, ReadingType::Pressure),
+			Some(ReadingThreshold { min: None, max: Some(Decimal::max_value()) })
+		);
+		let stats = Tracks::reading_stats(&shipment_id, ReadingType::Pressure).unwrap();
+		assert_eq!((stats.count, stats.min, stats.max), (2, Decimal::from_num(4.5), Decimal::max_value()));
+		assert_eq!(stats.sum, DecimalSum::from_num(40004.5));
+	})
+}
+
+#[test]
+fn register_shipment_beyond_organization_limit() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		for id in vec!["0001", "0002", "0003"] {
+			assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.as_bytes().to_owned(), owner, vec![], None, None, None));
+		}
+		assert_eq!(
+			Tracks::shipments_of_org(&owner, 1, 10),
+			vec![b"0002".to_vec(), b"0003".to_vec()]
+		);
+
+		assert_noop!(
+			Tracks::register_shipment(Origin::signed(owner), b"0004".to_vec(), owner, vec![], None, None, None),
+			Error::<TestRuntime>::OrganizationHasTooManyShipments
+		);
+	})
+}
+
+#[test]
+fn record_event_beyond_shipment_limit() {
+	ExtBuilder::build().execute_with(|| {
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::InTransit,
+			vec![],
+			42,
+		);
+		for id in vec!["0000000001", "0000000002", "0000000003", "0000000004"] {
+			assert_ok!(Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				test_event(id, &shipment_id, ShippingEventType::SensorReading)
+			));
+		}
+
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				test_event("0000000005", &shipment_id, ShippingEventType::SensorReading)
+			),
+			Error::<TestRuntime>::ShipmentHasTooManyEvents
+		);
+	})
+}
+
+#[test]
+fn register_device_works() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let device_id = TEST_DEVICE_ID.as_bytes().to_owned();
+
+		assert_ok!(Tracks::register_device(
+			Origin::signed(owner),
+			device_id.clone(),
+			device_pair().public(),
+			owner
+		));
+
+		let expected_event = TestEvent::vec_set(RawEvent::DeviceRegistered(owner, device_id.clone(), owner));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		assert_noop!(
+			Tracks::register_device(Origin::signed(owner), device_id.clone(), device_pair().public(), owner),
+			Error::<TestRuntime>::DeviceAlreadyRegistered
+		);
+
+		assert_ok!(Tracks::revoke_device(Origin::signed(owner), device_id.clone()));
+		assert_eq!(Tracks::device_by_id(&device_id).map(|d| d.revoked), Some(true));
+	})
+}
+
+#[test]
+fn register_device_as_non_delegate() {
+	ExtBuilder::build().execute_with(|| {
+		assert_noop!(
+			Tracks::register_device(
+				Origin::signed(account_key(TEST_SENDER)),
+				TEST_DEVICE_ID.as_bytes().to_owned(),
+				device_pair().public(),
+				account_key(TEST_ORGANIZATION)
+			),
+			Error::<TestRuntime>::SenderIsNotOwnerOrDelegate
+		);
+	})
+}
+
+#[test]
+fn record_event_with_unregistered_device() {
+	ExtBuilder::build().execute_with(|| {
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::InTransit,
+			vec![],
+			42,
+		);
+
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				ShippingEvent {
+					readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.0), 42)],
+					..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+				}
+			),
+			Error::<TestRuntime>::DeviceIsUnregistered
+		);
+	})
+}
+
+#[test]
+fn record_event_with_revoked_device() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+		store_test_device::<TestRuntime>(owner, true);
+
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				ShippingEvent {
+					readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.0), 42)],
+					..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+				}
+			),
+			Error::<TestRuntime>::DeviceIsRevoked
+		);
+	})
+}
+
+#[test]
+fn record_event_with_forged_reading() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+		store_test_device::<TestRuntime>(owner, false);
+
+		// The value was tampered with after the device signed it
+		let mut event = ShippingEvent {
+			readings: vec![signed_reading(&shipment_id, ReadingType::Temperature, Decimal::from_num(4.0), 42)],
+			..test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+		};
+		event.readings[0].value = Decimal::from_num(5.0);
+
+		assert_noop!(
+			Tracks::record_event(Origin::signed(account_key(TEST_SENDER)), event),
+			Error::<TestRuntime>::InvalidReadingSignature
+		);
+	})
+}
+
+#[test]
+fn custody_handoff_works() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let first_carrier = account_key(TEST_SENDER);
+		let second_carrier = account_key("Bob");
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+
+		// First leg
+		assert_ok!(Tracks::hand_over_custody(Origin::signed(owner), shipment_id.clone(), first_carrier));
+		assert_ok!(Tracks::accept_custody(
+			Origin::signed(first_carrier),
+			test_event("0000000001", &shipment_id, ShippingEventType::CustodyHandoff)
+		));
+
+		// Second leg
+		assert_ok!(Tracks::hand_over_custody(
+			Origin::signed(first_carrier),
+			shipment_id.clone(),
+			second_carrier
+		));
+		assert_ok!(Tracks::accept_custody(
+			Origin::signed(second_carrier),
+			test_event("0000000002", &shipment_id, ShippingEventType::CustodyHandoff)
+		));
+
+		let expected_event = TestEvent::vec_set(RawEvent::CustodyTransferred(
+			first_carrier,
+			second_carrier,
+			shipment_id.clone(),
+		));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		assert_eq!(Tracks::shipment_by_id(&shipment_id).map(|s| s.custodian), Some(second_carrier));
+		assert_eq!(Tracks::pending_handoff(&shipment_id), None);
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1, 2]);
+		assert_eq!(Tracks::custody_count(&shipment_id), 2);
+		assert_eq!(
+			Tracks::custody_record(&shipment_id, 0),
+			Some(CustodyRecord { from: owner, to: first_carrier, event_idx: 1, timestamp: 42 })
+		);
+		assert_eq!(
+			Tracks::custody_record(&shipment_id, 1),
+			Some(CustodyRecord { from: first_carrier, to: second_carrier, event_idx: 2, timestamp: 42 })
+		);
+	})
+}
+
+#[test]
+fn hand_over_custody_as_non_custodian() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+
+		assert_noop!(
+			Tracks::hand_over_custody(Origin::signed(account_key(TEST_SENDER)), shipment_id, account_key("Bob")),
+			Error::<TestRuntime>::SenderIsNotCustodian
+		);
+	})
+}
+
+#[test]
+fn accept_custody_as_other_party() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+
+		assert_noop!(
+			Tracks::accept_custody(
+				Origin::signed(account_key(TEST_SENDER)),
+				test_event("0000000001", &shipment_id, ShippingEventType::CustodyHandoff)
+			),
+			Error::<TestRuntime>::NoPendingCustodyHandoff
+		);
+
+		assert_ok!(Tracks::hand_over_custody(
+			Origin::signed(owner),
+			shipment_id.clone(),
+			account_key(TEST_SENDER)
+		));
+		assert_noop!(
+			Tracks::accept_custody(
+				Origin::signed(account_key("Bob")),
+				test_event("0000000001", &shipment_id, ShippingEventType::CustodyHandoff)
+			),
+			Error::<TestRuntime>::SenderIsNotCustodyRecipient
+		);
+	})
+}
+
+#[test]
+fn record_event_for_custody_handoff() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(owner),
+				test_event("0000000001", &shipment_id, ShippingEventType::CustodyHandoff)
+			),
+			Error::<TestRuntime>::CustodyHandoffMustBeAccepted
+		);
+	})
+}
+
+#[test]
+fn transfer_shipment_works() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let buyer = account_key(TEST_SENDER);
+		for id in vec!["0001", "0002", "0003"] {
+			assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.as_bytes().to_owned(), owner, vec![], None, None, None));
+		}
+		let shipment_id = b"0001".to_vec();
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			test_event("0000000001", &shipment_id, ShippingEventType::ShipmentPickup)
+		));
+
+		assert_ok!(Tracks::transfer_shipment(
+			Origin::signed(owner),
+			test_event("0000000002", &shipment_id, ShippingEventType::OwnershipTransfer),
+			buyer,
+			false
+		));
+
+		let shipment = Tracks::shipment_by_id(&shipment_id).unwrap();
+		assert_eq!(shipment.owner, buyer);
+		assert_eq!(shipment.custodian, owner);
+		assert_eq!(shipment.status, ShipmentStatus::InTransit);
+		// The other shipments keep their index
+		assert_eq!(Tracks::shipments_of_org(&owner, 0, 10), vec![b"0002".to_vec(), b"0003".to_vec()]);
+		assert_eq!(Tracks::shipment_of_org_by_idx(&owner, 0), None);
+		assert_eq!(Tracks::shipment_of_org_by_idx(&owner, 2), Some(b"0003".to_vec()));
+		assert_eq!(Tracks::shipment_idx_of_org(&owner, &shipment_id), None);
+		assert_eq!(Tracks::shipments_of_org(&buyer, 0, 10), vec![shipment_id.clone()]);
+		assert_eq!(Tracks::shipment_idx_of_org(&buyer, &shipment_id), Some(0));
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1, 2]);
+
+		let expected_event = TestEvent::vec_set(RawEvent::ShipmentTransferred(owner, buyer, shipment_id.clone()));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		// The former owner can't transfer the shipment anymore
+		assert_noop!(
+			Tracks::transfer_shipment(
+				Origin::signed(owner),
+				test_event("0000000003", &shipment_id, ShippingEventType::OwnershipTransfer),
+				owner,
+				false
+			),
+			Error::<TestRuntime>::SenderIsNotOwner
+		);
+	})
+}
+
+#[test]
+fn transfer_shipment_with_acceptance() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let buyer = account_key(TEST_SENDER);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), shipment_id.clone(), owner, vec![], None, None, None));
+
+		let event = test_event("0000000001", &shipment_id, ShippingEventType::OwnershipTransfer);
+		assert_ok!(Tracks::transfer_shipment(Origin::signed(owner), event.clone(), buyer, true));
+
+		// Nothing changes until the new owner accepts
+		assert_eq!(Tracks::shipment_by_id(&shipment_id).map(|s| s.owner), Some(owner));
+		assert_eq!(Tracks::pending_transfer(&shipment_id), Some((owner, buyer, event)));
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![]);
+		let expected_event = TestEvent::vec_set(RawEvent::ShipmentTransferOffered(owner, buyer, shipment_id.clone()));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		assert_noop!(
+			Tracks::accept_shipment_transfer(Origin::signed(account_key("Bob")), shipment_id.clone()),
+			Error::<TestRuntime>::SenderIsNotTransferRecipient
+		);
+		// The former owner's roles don't matter anymore
+		assert_ok!(Tracks::revoke_role(Origin::root(), owner, Role::Shipper));
+		assert_ok!(Tracks::accept_shipment_transfer(Origin::signed(buyer), shipment_id.clone()));
+		let expected_event = TestEvent::vec_set(RawEvent::ShippingEventRecorded(
+			buyer,
+			b"0000000001".to_vec(),
+			shipment_id.clone(),
+			ShippingEventType::OwnershipTransfer,
+		));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		assert_eq!(Tracks::shipment_by_id(&shipment_id).map(|s| s.owner), Some(buyer));
+		assert_eq!(Tracks::pending_transfer(&shipment_id), None);
+		assert_eq!(Tracks::shipments_of_org(&owner, 0, 10), vec![]);
+		assert_eq!(Tracks::shipments_of_org(&buyer, 0, 10), vec![shipment_id.clone()]);
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1]);
+		assert_noop!(
+			Tracks::accept_shipment_transfer(Origin::signed(buyer), shipment_id),
+			Error::<TestRuntime>::NoPendingShipmentTransfer
+		);
+	})
+}
+
+#[test]
+fn transfer_shipment_with_invalid_input() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+
+		assert_noop!(
+			Tracks::transfer_shipment(
+				Origin::signed(owner),
+				test_event("0000000001", &shipment_id, ShippingEventType::CustodyHandoff),
+				account_key("Bob"),
+				false
+			),
+			Error::<TestRuntime>::ShippingEventIsNotOwnershipTransfer
+		);
+		assert_noop!(
+			Tracks::transfer_shipment(
+				Origin::signed(owner),
+				test_event("0000000001", &shipment_id, ShippingEventType::OwnershipTransfer),
+				owner,
+				false
+			),
+			Error::<TestRuntime>::ShipmentTransferToSelf
+		);
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(owner),
+				test_event("0000000001", &shipment_id, ShippingEventType::OwnershipTransfer)
+			),
+			Error::<TestRuntime>::ShipmentTransferRequired
+		);
+
+		// The new owner must have room for the shipment
+		let buyer = account_key("Bob");
+		ShipmentCountOfOrganization::<TestRuntime>::insert(&buyer, 3);
+		assert_noop!(
+			Tracks::transfer_shipment(
+				Origin::signed(owner),
+				test_event("0000000001", &shipment_id, ShippingEventType::OwnershipTransfer),
+				buyer,
+				false
+			),
+			Error::<TestRuntime>::OrganizationHasTooManyShipments
+		);
+	})
+}
+
+fn store_test_geofence(shipment_id: &ShipmentId) {
+	// 500m around Alexanderplatz, Berlin
+	Geofences::insert(
+		shipment_id,
+		Geofence::Circle {
+			center: ReadPoint {
+				latitude: Coordinate::from_num(52.52),
+				longitude: Coordinate::from_num(13.405),
+			},
+			radius: 500,
+		},
+	);
+}
+
+#[test]
+fn set_geofence_works() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		let center = ReadPoint {
+			latitude: Coordinate::from_num(52.52),
+			longitude: Coordinate::from_num(13.405),
+		};
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::Pending, vec![], 42);
+
+		assert_noop!(
+			Tracks::set_geofence(
+				Origin::signed(owner),
+				shipment_id.clone(),
+				Geofence::Circle { center: center.clone(), radius: 0 }
+			),
+			Error::<TestRuntime>::InvalidGeofence
+		);
+
+		let geofence = Geofence::Circle { center, radius: 500 };
+		assert_ok!(Tracks::set_geofence(Origin::signed(owner), shipment_id.clone(), geofence.clone()));
+		assert_eq!(Tracks::geofence(&shipment_id), Some(geofence));
+	})
+}
+
+#[test]
+fn record_delivery_within_geofence() {
+	ExtBuilder::build().execute_with(|| {
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::InTransit,
+			vec![],
+			42,
+		);
+		store_test_geofence(&shipment_id);
+
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			ShippingEvent {
+				location: Some(ReadPoint { latitude: Coordinate::from_num(52.5209), longitude: Coordinate::from_num(13.405) }),
+				..test_event("0000000001", &shipment_id, ShippingEventType::ShipmentDelivery)
+			}
+		));
+		assert_eq!(
+			Tracks::shipment_by_id(&shipment_id).map(|s| s.status),
+			Some(ShipmentStatus::Delivered)
+		);
+	})
+}
+
+#[test]
+fn record_delivery_outside_geofence() {
+	ExtBuilder::build().execute_with(|| {
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::InTransit,
+			vec![],
+			42,
+		);
+		store_test_geofence(&shipment_id);
+
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				ShippingEvent {
+					location: Some(ReadPoint { latitude: Coordinate::from_num(52.529), longitude: Coordinate::from_num(13.405) }),
+					..test_event("0000000001", &shipment_id, ShippingEventType::ShipmentDelivery)
+				}
+			),
+			Error::<TestRuntime>::DeliveryOutsideGeofence
+		);
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				test_event("0000000001", &shipment_id, ShippingEventType::ShipmentDelivery)
+			),
+			Error::<TestRuntime>::DeliveryLocationMissing
+		);
+	})
+}
+
+#[test]
+fn record_delivery_outside_geofence_when_flagging() {
+	ExtBuilder::build().execute_with(|| {
+		REJECT_OUTSIDE_GEOFENCE.with(|v| *v.borrow_mut() = false);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::InTransit,
+			vec![],
+			42,
+		);
+		store_test_geofence(&shipment_id);
+
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			test_event("0000000001", &shipment_id, ShippingEventType::ShipmentDelivery)
+		));
+
+		let expected_event = TestEvent::vec_set(RawEvent::DeliveryOutsideGeofence(
+			shipment_id.clone(),
+			b"0000000001".to_vec(),
+		));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+		assert_eq!(
+			Tracks::shipment_by_id(&shipment_id).map(|s| s.status),
+			Some(ShipmentStatus::Delivered)
+		);
+	})
+}
+
+#[test]
+fn aggregated_shipments_follow_container() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let container_id = b"C001".to_vec();
+		let children = vec![b"0001".to_vec(), b"0002".to_vec()];
+
+		store_test_shipment::<TestRuntime>(container_id.clone(), owner, ShipmentStatus::Pending, vec![], 42);
+		for child_id in children.iter() {
+			store_test_shipment::<TestRuntime>(child_id.clone(), owner, ShipmentStatus::Pending, vec![], 42);
+		}
+
+		assert_ok!(Tracks::aggregate_shipments(Origin::signed(owner), container_id.clone(), children.clone()));
+		assert_eq!(Tracks::children_of(&container_id), children);
+		assert_eq!(Tracks::parent_of(&children[0]), Some(container_id.clone()));
+
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			test_event("0000000001", &container_id, ShippingEventType::ShipmentPickup)
+		));
+
+		for child_id in children.iter() {
+			assert_eq!(
+				Tracks::shipment_by_id(child_id).map(|s| s.status),
+				Some(ShipmentStatus::InTransit)
+			);
+			assert_eq!(Tracks::events_of_shipment(child_id, 0, 10), vec![1]);
+		}
+
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			test_event("0000000002", &container_id, ShippingEventType::ShipmentDelivery)
+		));
+		assert_ok!(Tracks::record_event(
+			Origin::signed(account_key(TEST_SENDER)),
+			test_event("0000000003", &container_id, ShippingEventType::ShipmentDisaggregation)
+		));
+
+		let expected_event = TestEvent::vec_set(RawEvent::ShipmentsDisaggregated(container_id.clone(), children.clone()));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		assert_eq!(Tracks::children_of(&container_id), Vec::<ShipmentId>::new());
+		for child_id in children.iter() {
+			assert_eq!(Tracks::parent_of(child_id), None);
+			assert_eq!(
+				Tracks::shipment_by_id(child_id).map(|s| s.status),
+				Some(ShipmentStatus::Delivered)
+			);
+			assert_eq!(Tracks::events_of_shipment(child_id, 0, 10), vec![1, 2, 3]);
+		}
+	})
+}
+
+#[test]
+fn container_event_with_child_in_wrong_status() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let container_id = b"C001".to_vec();
+		let child_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(container_id.clone(), owner, ShipmentStatus::InTransit, vec![], 42);
+		store_test_shipment::<TestRuntime>(child_id.clone(), owner, ShipmentStatus::Pending, vec![], 42);
+		assert_ok!(Tracks::aggregate_shipments(Origin::signed(owner), container_id.clone(), vec![child_id]));
+
+		// The child was never picked up
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				test_event("0000000001", &container_id, ShippingEventType::ShipmentDelivery)
+			),
+			Error::<TestRuntime>::ChildShipmentTransitionNotAllowed
+		);
+	})
+}
+
+#[test]
+fn aggregate_shipments_with_invalid_shipments() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let container_id = b"C001".to_vec();
+		let child_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		store_test_shipment::<TestRuntime>(container_id.clone(), owner, ShipmentStatus::Pending, vec![], 42);
+		store_test_shipment::<TestRuntime>(child_id.clone(), owner, ShipmentStatus::Pending, vec![], 42);
+
+		assert_noop!(
+			Tracks::aggregate_shipments(Origin::signed(owner), container_id.clone(), vec![]),
+			Error::<TestRuntime>::NoShipmentsToAggregate
+		);
+		assert_noop!(
+			Tracks::aggregate_shipments(
+				Origin::signed(owner),
+				container_id.clone(),
+				vec![child_id.clone(), child_id.clone()]
+			),
+			Error::<TestRuntime>::DuplicateShipmentInAggregation
+		);
+		assert_noop!(
+			Tracks::aggregate_shipments(Origin::signed(owner), container_id.clone(), vec![container_id.clone()]),
+			Error::<TestRuntime>::ShipmentIsContainer
+		);
+		assert_noop!(
+			Tracks::aggregate_shipments(
+				Origin::signed(account_key(TEST_SENDER)),
+				container_id.clone(),
+				vec![child_id.clone()]
+			),
+			Error::<TestRuntime>::SenderIsNotCustodian
+		);
+
+		assert_ok!(Tracks::aggregate_shipments(Origin::signed(owner), container_id.clone(), vec![child_id.clone()]));
+		assert_noop!(
+			Tracks::aggregate_shipments(Origin::signed(owner), container_id, vec![child_id]),
+			Error::<TestRuntime>::ShipmentIsAlreadyAggregated
+		);
+	})
+}
+
+#[test]
+fn delivered_shipment_events_are_pruned() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		Timestamp::set_timestamp(42);
+
+		store_test_shipment::<TestRuntime>(shipment_id.clone(), owner, ShipmentStatus::Pending, vec![], 42);
+		for (id, event_type) in vec![
+			("0000000001", ShippingEventType::ShipmentPickup),
+			("0000000002", ShippingEventType::SensorReading),
+			("0000000003", ShippingEventType::ShipmentDelivery),
+		] {
+			assert_ok!(Tracks::record_event(
+				Origin::signed(account_key(TEST_SENDER)),
+				test_event(id, &shipment_id, event_type)
+			));
+		}
+		let events = (1..=3).map(|idx| Tracks::event_by_idx(idx).unwrap()).collect::<Vec<_>>();
+
+		// Still within the retention period
+		Timestamp::set_timestamp(1041);
+		Tracks::on_initialize(2);
+		assert_eq!(Tracks::archived_root(&shipment_id), None);
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1, 2, 3]);
+
+		// At most two events are pruned per block
+		Timestamp::set_timestamp(1042);
+		Tracks::on_initialize(3);
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![3]);
+		assert_eq!(Tracks::event_by_idx(1), None);
+		assert_eq!(Tracks::event_idx_from_id(b"0000000001".to_vec()), None);
+
+		Tracks::on_initialize(4);
+		assert!(!EventCountOfShipment::contains_key(&shipment_id));
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![]);
+		assert_eq!(Tracks::event_by_idx(3), None);
+
+		// The archived root still proves the pruned events
+		let leaves = events.iter().map(|e| BlakeTwo256::hash_of(e)).collect::<Vec<_>>();
+		let root = Tracks::archived_root(&shipment_id).unwrap();
+		assert_eq!(root, merkle_root::<BlakeTwo256>(&leaves));
+		let proof = merkle_proof::<BlakeTwo256>(&leaves, 1);
+		assert!(verify_proof::<BlakeTwo256>(&root, leaves[1], 1, leaves.len(), &proof));
+
+		let expected_event = TestEvent::vec_set(RawEvent::ShipmentEventsArchived(shipment_id.clone(), root, 3));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		// The shipment itself is kept
+		assert_eq!(
+			Tracks::shipment_by_id(&shipment_id).map(|s| s.status),
+			Some(ShipmentStatus::Delivered)
+		);
+	})
+}
+
+#[test]
+fn record_events_works() {
+	ExtBuilder::build().execute_with(|| {
+		let sender = account_key(TEST_SENDER);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::Pending,
+			vec![],
+			42,
+		);
+
+		// Each event relies on the status left by the previous one
+		assert_ok!(Tracks::record_events(
+			Origin::signed(sender),
+			vec![
+				test_event("0000000001", &shipment_id, ShippingEventType::ShipmentPickup),
+				test_event("0000000002", &shipment_id, ShippingEventType::SensorReading),
+				test_event("0000000003", &shipment_id, ShippingEventType::ShipmentDelivery),
+			],
+			true
+		));
+
+		assert_eq!(Tracks::event_count(), 3);
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1, 2, 3]);
+		assert_eq!(Tracks::event_idx_from_id(b"0000000003".to_vec()), Some(3));
+		assert_eq!(
+			Tracks::shipment_by_id(&shipment_id).map(|s| s.status),
+			Some(ShipmentStatus::Delivered)
+		);
+		let expected_event = TestEvent::vec_set(RawEvent::ShippingEventBatchRecorded(sender, 3, 0));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn record_events_all_or_nothing() {
+	ExtBuilder::build().execute_with(|| {
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::Pending,
+			vec![],
+			42,
+		);
+
+		assert_noop!(
+			Tracks::record_events(
+				Origin::signed(account_key(TEST_SENDER)),
+				vec![
+					test_event("0000000001", &shipment_id, ShippingEventType::ShipmentPickup),
+					// Picked up twice
+					test_event("0000000002", &shipment_id, ShippingEventType::ShipmentPickup),
+				],
+				true
+			),
+			Error::<TestRuntime>::ShipmentIsInTransit
+		);
+	})
+}
+
+#[test]
+fn record_events_skips_rejected_events() {
+	ExtBuilder::build().execute_with(|| {
+		let sender = account_key(TEST_SENDER);
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		store_test_shipment::<TestRuntime>(
+			shipment_id.clone(),
+			account_key(TEST_ORGANIZATION),
+			ShipmentStatus::Pending,
+			vec![],
+			42,
+		);
+
+		assert_ok!(Tracks::record_events(
+			Origin::signed(sender),
+			vec![
+				test_event("0000000001", &shipment_id, ShippingEventType::ShipmentPickup),
+				test_event("0000000001", &shipment_id, ShippingEventType::SensorReading),
+				test_event("0000000002", b"0002", ShippingEventType::SensorReading),
+			],
+			false
+		));
+
+		assert_eq!(Tracks::events_of_shipment(&shipment_id, 0, 10), vec![1]);
+		assert_eq!(
+			Tracks::shipment_by_id(&shipment_id).map(|s| s.status),
+			Some(ShipmentStatus::InTransit)
+		);
+		let expected_events = vec![
+			RawEvent::ShippingEventRejected(
+				sender,
+				b"0000000001".to_vec(),
+				Error::<TestRuntime>::ShippingEventAlreadyExists.into(),
+			),
+			RawEvent::ShippingEventRejected(sender, b"0000000002".to_vec(), Error::<TestRuntime>::ShipmentIsUnknown.into()),
+			RawEvent::ShippingEventBatchRecorded(sender, 1, 2),
+		];
+		for expected_event in expected_events {
+			let expected_event = TestEvent::vec_set(expected_event);
+			assert!(System::events().iter().any(|a| a.event == expected_event));
+		}
+	})
+}
+
+#[test]
+fn record_events_with_invalid_batch_size() {
+	ExtBuilder::build().execute_with(|| {
+		let shipment_id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		assert_noop!(
+			Tracks::record_events(Origin::signed(account_key(TEST_SENDER)), vec![], true),
+			Error::<TestRuntime>::EmptyShippingEventBatch
+		);
+		assert_noop!(
+			Tracks::record_events(
+				Origin::signed(account_key(TEST_SENDER)),
+				vec![
+					test_event("0000000001", &shipment_id, ShippingEventType::SensorReading),
+					test_event("0000000002", &shipment_id, ShippingEventType::SensorReading),
+					test_event("0000000003", &shipment_id, ShippingEventType::SensorReading),
+					test_event("0000000004", &shipment_id, ShippingEventType::SensorReading),
+				],
+				false
+			),
+			Error::<TestRuntime>::ShippingEventBatchTooLarge
+		);
+	})
+}
+
+#[test]
+fn register_shipment_with_deadline() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		Timestamp::set_timestamp(100);
+
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), b"0001".to_vec(), owner, vec![], Some(2000), None, None));
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), b"0002".to_vec(), owner, vec![], Some(1000), None, None));
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), b"0003".to_vec(), owner, vec![], None, None, None));
+
+		assert_eq!(Tracks::delivery_deadline(b"0001".to_vec()), Some(2000));
+		assert_eq!(Tracks::delivery_deadline(b"0003".to_vec()), None);
+		// Deadlines are queued in buckets of 100ms, checked from the current one
+		assert_eq!(Tracks::deadline_by_idx(20, 0), Some(b"0001".to_vec()));
+		assert_eq!(Tracks::deadline_by_idx(10, 0), Some(b"0002".to_vec()));
+		assert_eq!(Tracks::deadline_count_of_bucket(10), 1);
+		assert_eq!(Tracks::queued_deadlines(), 2);
+		assert_eq!(Tracks::deadline_cursor(), (1, 0));
+	})
+}
+
+#[test]
+fn register_shipment_with_past_deadline() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		Timestamp::set_timestamp(1000);
+
+		assert_noop!(
+			Tracks::register_shipment(Origin::signed(owner), b"0001".to_vec(), owner, vec![], Some(1000), None, None),
+			Error::<TestRuntime>::DeliveryDeadlineInPast
+		);
+	})
+}
+
+#[test]
+fn integrity_test_checks_deadline_bucket_size() {
+	ExtBuilder::build().execute_with(|| {
+		assert!(DeadlineBucketSize::get() > 0);
+		<Tracks as IntegrityTest>::integrity_test();
+	})
+}
+
+#[test]
+fn overdue_shipments_are_reported() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		Timestamp::set_timestamp(100);
+		for (id, deadline) in [(b"0001", 150), (b"0002", 250), (b"0003", 450)].iter() {
+			assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.to_vec(), owner, vec![], Some(*deadline), None, None));
+		}
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			test_event("0000000001", b"0002", ShippingEventType::ShipmentCancellation)
+		));
+		let overdue = |id: &[u8], deadline| TestEvent::vec_set(RawEvent::ShipmentOverdue(id.to_vec(), deadline));
+
+		// Deadlines are checked once their whole bucket elapsed
+		Timestamp::set_timestamp(199);
+		Tracks::on_finalize(1);
+		assert_eq!(Tracks::queued_deadlines(), 3);
+		assert_eq!(Tracks::deadline_cursor(), (1, 0));
+
+		// Up to 2 deadlines or buckets are checked in a block
+		Timestamp::set_timestamp(1000);
+		Tracks::on_finalize(2);
+		assert!(System::events().iter().any(|a| a.event == overdue(b"0001", 150)));
+		assert_eq!(Tracks::deadline_cursor(), (3, 0));
+		assert_eq!(Tracks::queued_deadlines(), 1);
+
+		// The cancelled shipment isn't reported
+		assert!(!System::events().iter().any(|a| a.event == overdue(b"0002", 250)));
+
+		// Skipping the empty bucket counts as a check
+		Tracks::on_finalize(3);
+		assert!(System::events().iter().any(|a| a.event == overdue(b"0003", 450)));
+		assert_eq!(Tracks::deadline_cursor(), (5, 0));
+		assert_eq!(Tracks::queued_deadlines(), 0);
+		assert_eq!(Tracks::deadline_count_of_bucket(4), 0);
+
+		// Once none is left, new deadlines are checked from the current bucket
+		let sender = account_key(TEST_SENDER);
+		assert_ok!(Tracks::register_shipment(Origin::signed(sender), b"0004".to_vec(), sender, vec![], Some(2000), None, None));
+		assert_eq!(Tracks::deadline_cursor(), (10, 0));
+	})
+}
+
+#[test]
+fn delivery_records_lateness() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), b"0001".to_vec(), owner, vec![], Some(100), None, None));
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), b"0002".to_vec(), owner, vec![], Some(1000), None, None));
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), b"0003".to_vec(), owner, vec![], None, None, None));
+
+		Timestamp::set_timestamp(400);
+		for (idx, id) in [b"0001", b"0002", b"0003"].iter().enumerate() {
+			assert_ok!(Tracks::record_events(
+				Origin::signed(owner),
+				vec![
+					test_event(&format!("00000000{}1", idx), &id.to_vec(), ShippingEventType::ShipmentPickup),
+					test_event(&format!("00000000{}2", idx), &id.to_vec(), ShippingEventType::ShipmentDelivery),
+				],
+				true
+			));
+		}
+
+		assert_eq!(Tracks::delivery_lateness(b"0001".to_vec()), Some(300));
+		assert_eq!(Tracks::delivery_lateness(b"0002".to_vec()), Some(0));
+		assert_eq!(Tracks::delivery_lateness(b"0003".to_vec()), None);
+	})
+}
+
+#[test]
+fn payment_is_released_on_delivery() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let carrier = account_key(TEST_SENDER);
+		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		basic_token::Balances::<TestRuntime>::insert(owner, 1000);
+
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.clone(), owner, vec![], None, Some((carrier, 300)), None));
+		assert_eq!(BasicToken::get_balance(owner), 700);
+		assert_eq!(BasicToken::locked_balance(owner), 300);
+		assert_eq!(Tracks::payment(&id), Some(Payment { payer: owner, carrier, amount: 300 }));
+		let expected_event = TestEvent::vec_set(RawEvent::PaymentLocked(id.clone(), owner, carrier, 300));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		assert_ok!(Tracks::record_events(
+			Origin::signed(carrier),
+			vec![
+				test_event("0000000001", &id, ShippingEventType::ShipmentPickup),
+				test_event("0000000002", &id, ShippingEventType::ShipmentDelivery),
+			],
+			true
+		));
+
+		assert_eq!(BasicToken::get_balance(carrier), 300);
+		assert_eq!(BasicToken::locked_balance(owner), 0);
+		assert_eq!(Tracks::payment(&id), None);
+		let expected_event = TestEvent::vec_set(RawEvent::PaymentReleased(id, carrier, 300));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn payment_is_refunded_on_cancellation() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let carrier = account_key(TEST_SENDER);
+		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		basic_token::Balances::<TestRuntime>::insert(owner, 1000);
+
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.clone(), owner, vec![], None, Some((carrier, 300)), None));
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			test_event("0000000001", &id, ShippingEventType::ShipmentCancellation)
+		));
+
+		assert_eq!(BasicToken::get_balance(owner), 1000);
+		assert_eq!(BasicToken::get_balance(carrier), 0);
+		assert_eq!(Tracks::payment(&id), None);
+		let expected_event = TestEvent::vec_set(RawEvent::PaymentRefunded(id, owner, 300));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn payment_is_refunded_when_returned_or_lost() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let carrier = account_key(TEST_SENDER);
+		basic_token::Balances::<TestRuntime>::insert(owner, 1000);
+
+		for (idx, (id, event_type)) in [
+			(b"0001", ShippingEventType::ShipmentReturn),
+			(b"0002", ShippingEventType::ShipmentLoss),
+		]
+		.iter()
+		.enumerate()
+		{
+			let id = id.to_vec();
+			assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.clone(), owner, vec![], None, Some((carrier, 300)), None));
+			assert_ok!(Tracks::record_events(
+				Origin::signed(carrier),
+				vec![
+					test_event(&format!("00000000{}1", idx), &id, ShippingEventType::ShipmentPickup),
+					test_event(&format!("00000000{}2", idx), &id, event_type.clone()),
+				],
+				true
+			));
+
+			assert_eq!(Tracks::payment(&id), None);
+			let expected_event = TestEvent::vec_set(RawEvent::PaymentRefunded(id, owner, 300));
+			assert!(System::events().iter().any(|a| a.event == expected_event));
+		}
+
+		assert_eq!(BasicToken::get_balance(owner), 1000);
+		assert_eq!(BasicToken::locked_balance(owner), 0);
+		assert_eq!(BasicToken::get_balance(carrier), 0);
+	})
+}
+
+#[test]
+fn payment_is_kept_when_held_past_deadline() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let carrier = account_key(TEST_SENDER);
+		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		basic_token::Balances::<TestRuntime>::insert(owner, 1000);
+
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.clone(), owner, vec![], Some(100), Some((carrier, 300)), None));
+		assert_ok!(Tracks::record_events(
+			Origin::signed(carrier),
+			vec![
+				test_event("0000000001", &id, ShippingEventType::ShipmentPickup),
+				test_event("0000000002", &id, ShippingEventType::ShipmentHold),
+			],
+			true
+		));
+
+		Timestamp::set_timestamp(1000);
+		Tracks::on_finalize(1);
+
+		// Only pending and in transit shipments are overdue
+		assert!(Tracks::payment(&id).is_some());
+		assert_eq!(BasicToken::locked_balance(owner), 300);
+		let overdue = TestEvent::vec_set(RawEvent::ShipmentOverdue(id, 100));
+		assert!(!System::events().iter().any(|a| a.event == overdue));
+	})
+}
+
+#[test]
+fn payment_is_refunded_when_overdue() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let carrier = account_key(TEST_SENDER);
+		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		basic_token::Balances::<TestRuntime>::insert(owner, 1000);
+
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.clone(), owner, vec![], Some(100), Some((carrier, 300)), None));
+		Timestamp::set_timestamp(1000);
+		Tracks::on_finalize(1);
+		assert_eq!(BasicToken::get_balance(owner), 1000);
+		assert_eq!(Tracks::payment(&id), None);
+
+		// The carrier isn't paid for a late delivery
+		assert_ok!(Tracks::record_events(
+			Origin::signed(carrier),
+			vec![
+				test_event("0000000001", &id, ShippingEventType::ShipmentPickup),
+				test_event("0000000002", &id, ShippingEventType::ShipmentDelivery),
+			],
+			true
+		));
+		assert_eq!(BasicToken::get_balance(carrier), 0);
+	})
+}
+
+#[test]
+fn register_shipment_with_unaffordable_payment() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		basic_token::Balances::<TestRuntime>::insert(owner, 100);
+
+		assert_noop!(
+			Tracks::register_shipment(
+				Origin::signed(owner),
+				TEST_SHIPMENT_ID.as_bytes().to_owned(),
+				owner,
+				vec![],
+				None,
+				Some((account_key(TEST_SENDER), 300)),
+				None
+			),
+			Error::<TestRuntime>::InsufficientFundsForPayment
+		);
+	})
+}
+
+// Registers a shipment delivered to TEST_SENDER, and records its delivery
+fn deliver_to_consignee(id: &ShipmentId) {
+	let owner = account_key(TEST_ORGANIZATION);
+	let consignee = account_key(TEST_SENDER);
+	assert_ok!(Tracks::register_shipment(
+		Origin::signed(owner),
+		id.clone(),
+		owner,
+		vec![],
+		Some(1000),
+		Some((owner, 300)),
+		Some(consignee)
+	));
+	assert_ok!(Tracks::record_events(
+		Origin::signed(owner),
+		vec![
+			test_event("0000000001", id, ShippingEventType::ShipmentPickup),
+			test_event("0000000002", id, ShippingEventType::ShipmentDelivery),
+		],
+		true
+	));
+}
+
+#[test]
+fn delivery_awaits_consignee_acknowledgment() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		basic_token::Balances::<TestRuntime>::insert(owner, 1000);
+		deliver_to_consignee(&id);
+
+		let shipment = Tracks::shipment_by_id(&id).unwrap();
+		assert_eq!(shipment.status, ShipmentStatus::AwaitingAcknowledgment);
+		assert_eq!(shipment.delivered, None);
+		assert_eq!(Tracks::pending_acknowledgment(&id), Some((0, 100)));
+		assert_eq!(BasicToken::locked_balance(owner), 300);
+
+		Timestamp::set_timestamp(50);
+		assert_ok!(Tracks::confirm_delivery(Origin::signed(account_key(TEST_SENDER)), id.clone()));
+
+		let shipment = Tracks::shipment_by_id(&id).unwrap();
+		assert_eq!(shipment.status, ShipmentStatus::Delivered);
+		assert_eq!(shipment.delivered, Some(0));
+		assert_eq!(Tracks::pending_acknowledgment(&id), None);
+		assert_eq!(Tracks::delivery_lateness(&id), Some(0));
+		assert_eq!(BasicToken::locked_balance(owner), 0);
+		let expected_event = TestEvent::vec_set(RawEvent::DeliveryConfirmed(account_key(TEST_SENDER), id));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn delivery_acknowledgment_by_others() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.clone(), owner, vec![], None, None, Some(account_key(TEST_SENDER))));
+		assert_noop!(
+			Tracks::confirm_delivery(Origin::signed(account_key(TEST_SENDER)), id.clone()),
+			Error::<TestRuntime>::ShipmentIsNotAwaitingAcknowledgment
+		);
+
+		assert_ok!(Tracks::record_events(
+			Origin::signed(owner),
+			vec![
+				test_event("0000000001", &id, ShippingEventType::ShipmentPickup),
+				test_event("0000000002", &id, ShippingEventType::ShipmentDelivery),
+			],
+			true
+		));
+		assert_noop!(
+			Tracks::confirm_delivery(Origin::signed(owner), id.clone()),
+			Error::<TestRuntime>::SenderIsNotConsignee
+		);
+		assert_noop!(
+			Tracks::dispute_delivery(Origin::signed(owner), id),
+			Error::<TestRuntime>::SenderIsNotConsignee
+		);
+	})
+}
+
+#[test]
+fn disputed_delivery_is_held() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		basic_token::Balances::<TestRuntime>::insert(owner, 1000);
+		deliver_to_consignee(&id);
+
+		assert_ok!(Tracks::dispute_delivery(Origin::signed(account_key(TEST_SENDER)), id.clone()));
+
+		let shipment = Tracks::shipment_by_id(&id).unwrap();
+		assert_eq!(shipment.status, ShipmentStatus::Held);
+		assert_eq!(shipment.delivered, None);
+		assert_eq!(Tracks::pending_acknowledgment(&id), None);
+		// The payment stays in escrow until the dispute is settled
+		assert_eq!(BasicToken::locked_balance(owner), 300);
+
+		// The expired window doesn't deliver the shipment anymore
+		Timestamp::set_timestamp(1000);
+		Tracks::on_finalize(1);
+		assert_eq!(Tracks::shipment_by_id(&id).map(|s| s.status), Some(ShipmentStatus::Held));
+	})
+}
+
+#[test]
+fn delivery_is_confirmed_when_window_expires() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		basic_token::Balances::<TestRuntime>::insert(owner, 1000);
+		deliver_to_consignee(&id);
+
+		Timestamp::set_timestamp(100);
+		Tracks::on_finalize(1);
+		assert_eq!(
+			Tracks::shipment_by_id(&id).map(|s| s.status),
+			Some(ShipmentStatus::AwaitingAcknowledgment)
+		);
+
+		Timestamp::set_timestamp(101);
+		assert_noop!(
+			Tracks::dispute_delivery(Origin::signed(account_key(TEST_SENDER)), id.clone()),
+			Error::<TestRuntime>::AcknowledgmentWindowExpired
+		);
+		Tracks::on_finalize(2);
+
+		let shipment = Tracks::shipment_by_id(&id).unwrap();
+		assert_eq!(shipment.status, ShipmentStatus::Delivered);
+		assert_eq!(shipment.delivered, Some(0));
+		assert_eq!(BasicToken::get_balance(owner), 1000);
+		let expected_event = TestEvent::vec_set(RawEvent::DeliveryAcknowledgmentExpired(id));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+fn genesis_device(owner: sr25519::Public) -> Device<sr25519::Public> {
+	Device {
+		id: TEST_DEVICE_ID.as_bytes().to_owned(),
+		public_key: device_pair().public(),
+		owner,
+		revoked: false,
+	}
+}
+
+#[test]
+fn genesis_config_preloads_state() {
+	let owner = account_key(TEST_ORGANIZATION);
+	let carrier = account_key("Bob");
+	let reading = ShippingEvent {
+		readings: vec![signed_reading(b"0002", ReadingType::Temperature, Decimal::from_num(4.5), 35)],
+		timestamp: 40,
+		..test_event("0000000003", b"0002", ShippingEventType::SensorReading)
+	};
+	let events = vec![
+		ShippingEvent { timestamp: 30, ..test_event("0000000001", b"0001", ShippingEventType::ShipmentPickup) },
+		ShippingEvent { timestamp: 35, ..test_event("0000000002", b"0002", ShippingEventType::ShipmentPickup) },
+		reading,
+		ShippingEvent { timestamp: 50, ..test_event("0000000004", b"0001", ShippingEventType::ShipmentDelivery) },
+	];
+	let config = GenesisConfig::<TestRuntime> {
+		members: vec![(owner, vec![Role::Shipper]), (carrier, vec![Role::Carrier, Role::SensorGateway])],
+		shipments: vec![
+			(b"0001".to_vec(), owner, ShipmentStatus::Delivered, vec![], 10),
+			(b"0002".to_vec(), owner, ShipmentStatus::InTransit, vec![], 20),
+		],
+		devices: vec![genesis_device(owner)],
+		events: events.iter().map(|event| (carrier, event.clone())).collect(),
+	};
+
+	ExtBuilder::build_with_genesis(config).execute_with(|| {
+		assert_eq!(Tracks::roles_of(carrier), vec![Role::Carrier, Role::SensorGateway]);
+		assert_eq!(MemberCount::get(), 2);
+		assert_eq!(Tracks::device_by_id(TEST_DEVICE_ID.as_bytes().to_owned()), Some(genesis_device(owner)));
+
+		assert_eq!(Tracks::shipments_of_org(&owner, 0, 10), vec![b"0001".to_vec(), b"0002".to_vec()]);
+		let delivered = Tracks::shipment_by_id(b"0001".to_vec()).unwrap();
+		assert_eq!(delivered.status, ShipmentStatus::Delivered);
+		assert_eq!(delivered.registered, 10);
+		assert_eq!(delivered.delivered, Some(50));
+		assert_eq!(Tracks::shipment_by_id(b"0002".to_vec()).unwrap().status, ShipmentStatus::InTransit);
+
+		assert_eq!(Tracks::event_count(), 4);
+		assert_eq!(Tracks::event_idx_from_id(b"0000000004".to_vec()), Some(4));
+		assert_eq!(Tracks::shipment_timeline(b"0001"), vec![events[0].clone(), events[3].clone()]);
+		assert_eq!(Tracks::shipment_timeline(b"0002"), vec![events[1].clone(), events[2].clone()]);
+		assert_eq!(Tracks::reading_stats(b"0002".to_vec(), ReadingType::Temperature).map(|stats| stats.count), Some(1));
+
+		// Preloaded shipments take events recorded afterwards
+		Timestamp::set_timestamp(60);
+		assert_ok!(Tracks::record_event(
+			Origin::signed(carrier),
+			ShippingEvent { timestamp: 60, ..test_event("0000000005", b"0002", ShippingEventType::ShipmentDelivery) }
+		));
+		assert_eq!(Tracks::event_count(), 5);
+		assert_eq!(Tracks::shipment_by_id(b"0002".to_vec()).unwrap().status, ShipmentStatus::Delivered);
+	});
+}
+
+#[test]
+#[should_panic(expected = "Invalid genesis event: ShipmentIsUnknown")]
+fn genesis_config_rejects_events_of_unknown_shipments() {
+	let carrier = account_key("Bob");
+	let config = GenesisConfig::<TestRuntime> {
+		members: vec![(carrier, vec![Role::Carrier])],
+		shipments: vec![],
+		devices: vec![],
+		events: vec![(carrier, test_event("0000000001", b"0001", ShippingEventType::ShipmentPickup))],
+	};
+	ExtBuilder::build_with_genesis(config);
+}
+
+#[test]
+#[should_panic(expected = "Invalid genesis event: ShipmentStatusTransitionNotAllowed")]
+fn genesis_config_rejects_events_the_status_doesnt_allow() {
+	let (owner, carrier) = (account_key(TEST_ORGANIZATION), account_key("Bob"));
+	// The shipment is delivered without being picked up
+	let config = GenesisConfig::<TestRuntime> {
+		members: vec![(carrier, vec![Role::Carrier])],
+		shipments: vec![(b"0001".to_vec(), owner, ShipmentStatus::Delivered, vec![], 10)],
+		devices: vec![],
+		events: vec![(carrier, test_event("0000000001", b"0001", ShippingEventType::ShipmentDelivery))],
+	};
+	ExtBuilder::build_with_genesis(config);
+}
+
+#[test]
+#[should_panic(expected = "Invalid genesis event: SenderLacksRole")]
+fn genesis_config_rejects_events_of_members_lacking_roles() {
+	let (owner, carrier) = (account_key(TEST_ORGANIZATION), account_key("Bob"));
+	let config = GenesisConfig::<TestRuntime> {
+		members: vec![(carrier, vec![Role::Auditor])],
+		shipments: vec![(b"0001".to_vec(), owner, ShipmentStatus::InTransit, vec![], 10)],
+		devices: vec![],
+		events: vec![(carrier, test_event("0000000001", b"0001", ShippingEventType::ShipmentPickup))],
+	};
+	ExtBuilder::build_with_genesis(config);
+}
+
+#[test]
+#[should_panic(expected = "Invalid genesis event: DeviceIsUnregistered")]
+fn genesis_config_rejects_readings_of_unknown_devices() {
+	let (owner, carrier) = (account_key(TEST_ORGANIZATION), account_key("Bob"));
+	let event = ShippingEvent {
+		readings: vec![signed_reading(b"0001", ReadingType::Temperature, Decimal::from_num(4.5), 25)],
+		..test_event("0000000001", b"0001", ShippingEventType::ShipmentPickup)
+	};
+	let config = GenesisConfig::<TestRuntime> {
+		members: vec![(carrier, vec![Role::Carrier])],
+		shipments: vec![(b"0001".to_vec(), owner, ShipmentStatus::InTransit, vec![], 10)],
+		devices: vec![],
+		events: vec![(carrier, event)],
+	};
+	ExtBuilder::build_with_genesis(config);
+}
+
+#[test]
+#[should_panic(expected = "Invalid genesis event: CustodyHandoffMustBeAccepted")]
+fn genesis_config_rejects_custody_handoffs() {
+	let (owner, carrier) = (account_key(TEST_ORGANIZATION), account_key("Bob"));
+	let config = GenesisConfig::<TestRuntime> {
+		members: vec![(carrier, vec![Role::Carrier])],
+		shipments: vec![(b"0001".to_vec(), owner, ShipmentStatus::InTransit, vec![], 10)],
+		devices: vec![],
+		events: vec![
+			(carrier, test_event("0000000001", b"0001", ShippingEventType::ShipmentPickup)),
+			(carrier, test_event("0000000002", b"0001", ShippingEventType::CustodyHandoff)),
+		],
+	};
+	ExtBuilder::build_with_genesis(config);
+}
+
+#[test]
+#[should_panic(expected = "Invalid genesis event: ShipmentTransferRequired")]
+fn genesis_config_rejects_ownership_transfers() {
+	let owner = account_key(TEST_ORGANIZATION);
+	let config = GenesisConfig::<TestRuntime> {
+		members: vec![(owner, vec![Role::Shipper])],
+		shipments: vec![(b"0001".to_vec(), owner, ShipmentStatus::Pending, vec![], 10)],
+		devices: vec![],
+		events: vec![(owner, test_event("0000000001", b"0001", ShippingEventType::OwnershipTransfer))],
+	};
+	ExtBuilder::build_with_genesis(config);
+}
+
+#[test]
+#[should_panic(expected = "Genesis shipment status doesn't match its events")]
+fn genesis_config_rejects_delivered_shipments_without_delivery() {
+	let (owner, carrier) = (account_key(TEST_ORGANIZATION), account_key("Bob"));
+	let config = GenesisConfig::<TestRuntime> {
+		members: vec![(carrier, vec![Role::Carrier])],
+		shipments: vec![(b"0001".to_vec(), owner, ShipmentStatus::Delivered, vec![], 10)],
+		devices: vec![],
+		events: vec![(carrier, test_event("0000000001", b"0001", ShippingEventType::ShipmentPickup))],
+	};
+	ExtBuilder::build_with_genesis(config);
+}
+
+#[test]
+fn recall_product_holds_open_shipments() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let product_id = TEST_PRODUCT_ID.as_bytes().to_owned();
+		assert_ok!(SimpleMap::register_product(Origin::signed(owner), product_id.clone(), owner, None));
+		let register = |id: &str| {
+			Tracks::register_shipment(Origin::signed(owner), id.as_bytes().to_owned(), owner, vec![product_id.clone()], None, None, None)
+		};
+
+		assert_ok!(register("0001"));
+		assert_ok!(register("0002"));
+		assert_ok!(register("0003"));
+
+		// Delivered shipments stay indexed, but aren't located
+		let (first, second, third) = (b"0001".to_vec(), b"0002".to_vec(), b"0003".to_vec());
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			test_event("0000000001", &second, ShippingEventType::ShipmentPickup)
+		));
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			test_event("0000000002", &second, ShippingEventType::ShipmentDelivery)
+		));
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			test_event("0000000003", &third, ShippingEventType::ShipmentPickup)
+		));
+		assert_eq!(Tracks::shipment_count_of_product(&product_id), 3);
+		assert_eq!(
+			Tracks::product_locations(&product_id, 0, 10),
+			(vec![(first.clone(), ShipmentStatus::Pending, owner), (third.clone(), ShipmentStatus::InTransit, owner)], None)
+		);
+		// Pages are limited in shipments returned, and point to the next index
+		assert_eq!(
+			Tracks::product_locations(&product_id, 0, 1),
+			(vec![(first.clone(), ShipmentStatus::Pending, owner)], Some(1))
+		);
+		assert_eq!(
+			Tracks::product_locations(&product_id, 1, 1),
+			(vec![(third.clone(), ShipmentStatus::InTransit, owner)], None)
+		);
+
+		assert_noop!(
+			Tracks::recall_product(Origin::signed(account_key(TEST_SENDER)), product_id.clone()),
+			Error::<TestRuntime>::SenderIsNotProductOwner
+		);
+		assert_ok!(Tracks::recall_product(Origin::signed(owner), product_id.clone()));
+		assert_eq!(Tracks::recalled_on(&product_id), Some(0));
+		assert_eq!(Tracks::shipment_by_id(&first).map(|s| s.status), Some(ShipmentStatus::Pending));
+
+		// Two shipments are checked per block
+		Tracks::on_initialize(2);
+		assert_eq!(Tracks::shipment_by_id(&first).map(|s| s.status), Some(ShipmentStatus::Held));
+		assert_eq!(Tracks::shipment_by_id(&second).map(|s| s.status), Some(ShipmentStatus::Delivered));
+		assert_eq!(Tracks::shipment_by_id(&third).map(|s| s.status), Some(ShipmentStatus::InTransit));
+		Tracks::on_initialize(3);
+		assert_eq!(Tracks::shipment_by_id(&third).map(|s| s.status), Some(ShipmentStatus::Held));
+		for id in vec![first.clone(), third.clone()] {
+			let expected_event = TestEvent::vec_set(RawEvent::ShipmentRecalled(product_id.clone(), id));
+			assert!(System::events().iter().any(|a| a.event == expected_event));
+		}
+		assert_eq!(Tracks::product_locations(&product_id, 0, 10).0.len(), 2);
+
+		assert_noop!(
+			Tracks::recall_product(Origin::signed(owner), product_id.clone()),
+			Error::<TestRuntime>::ProductAlreadyRecalled
+		);
+		let other_owner = account_key(TEST_SENDER);
+		assert_noop!(
+			Tracks::register_shipment(Origin::signed(other_owner), b"0004".to_vec(), other_owner, vec![product_id], None, None, None),
+			Error::<TestRuntime>::ProductIsRecalled
+		);
+	})
+}
+
+#[test]
+fn recalled_shipments_cannot_move_on() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let product_id = TEST_PRODUCT_ID.as_bytes().to_owned();
+		let (shipment_id, container_id) = (b"0001".to_vec(), b"C001".to_vec());
+		assert_ok!(SimpleMap::register_product(Origin::signed(owner), product_id.clone(), owner, None));
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), shipment_id.clone(), owner, vec![product_id.clone()], None, None, None));
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), container_id.clone(), owner, vec![], None, None, None));
+		assert_ok!(Tracks::aggregate_shipments(Origin::signed(owner), container_id.clone(), vec![shipment_id.clone()]));
+		assert_ok!(Tracks::recall_product(Origin::signed(owner), product_id));
+
+		// Neither the shipment nor its container can be picked up
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(owner),
+				test_event("0000000001", &shipment_id, ShippingEventType::ShipmentPickup)
+			),
+			Error::<TestRuntime>::ShipmentIsRecalled
+		);
+		assert_noop!(
+			Tracks::record_event(
+				Origin::signed(owner),
+				test_event("0000000001", &container_id, ShippingEventType::ShipmentPickup)
+			),
+			Error::<TestRuntime>::ShipmentIsRecalled
+		);
+
+		// Events keeping its status are still recorded, and it can be given up
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			test_event("0000000001", &shipment_id, ShippingEventType::SensorReading)
+		));
+		assert_ok!(Tracks::record_event(
+			Origin::signed(owner),
+			test_event("0000000002", &shipment_id, ShippingEventType::ShipmentCancellation)
+		));
+	})
+}
+
+#[test]
+fn recall_holds_deliveries_awaiting_acknowledgment() {
+	ExtBuilder::build().execute_with(|| {
+		let owner = account_key(TEST_ORGANIZATION);
+		let consignee = account_key(TEST_SENDER);
+		let product_id = TEST_PRODUCT_ID.as_bytes().to_owned();
+		let id = TEST_SHIPMENT_ID.as_bytes().to_owned();
+		assert_ok!(SimpleMap::register_product(Origin::signed(owner), product_id.clone(), owner, None));
+		assert_ok!(Tracks::register_shipment(Origin::signed(owner), id.clone(), owner, vec![product_id.clone()], None, None, Some(consignee)));
+		assert_ok!(Tracks::record_events(
+			Origin::signed(owner),
+			vec![
+				test_event("0000000001", &id, ShippingEventType::ShipmentPickup),
+				test_event("0000000002", &id, ShippingEventType::ShipmentDelivery),
+			],
+			true
+		));
+
+		assert_ok!(Tracks::recall_product(Origin::signed(owner), product_id));
+		assert_noop!(
+			Tracks::confirm_delivery(Origin::signed(consignee), id.clone()),
+			Error::<TestRuntime>::ShipmentIsRecalled
+		);
+
+		// The window's expiry doesn't deliver the shipment, even before it's held
+		Timestamp::set_timestamp(1000);
+		Tracks::on_finalize(1);
+		assert_eq!(Tracks::shipment_by_id(&id).map(|s| s.status), Some(ShipmentStatus::AwaitingAcknowledgment));
+
+		Tracks::on_initialize(2);
+		assert_eq!(Tracks::shipment_by_id(&id).map(|s| s.status), Some(ShipmentStatus::Held));
+		assert_eq!(Tracks::pending_acknowledgment(&id), None);
+	})
+}
+
+#[test]
+fn recall_product_requires_shipper_role() {
+	ExtBuilder::build().execute_with(|| {
+		// Bob is only a carrier
+		let owner = account_key("Bob");
+		let product_id = TEST_PRODUCT_ID.as_bytes().to_owned();
+		assert_ok!(SimpleMap::register_product(Origin::signed(owner), product_id.clone(), owner, None));
+
+		assert_noop!(
+			Tracks::recall_product(Origin::signed(owner), product_id.clone()),
+			Error::<TestRuntime>::SenderLacksRole
+		);
+
+		assert_ok!(Tracks::grant_role(Origin::root(), owner, Role::Shipper));
+		assert_ok!(Tracks::recall_product(Origin::signed(owner), product_id));
+	})
+}
+
+#[test]
+fn recall_unknown_product() {
+	ExtBuilder::build().execute_with(|| {
+		assert_noop!(
+			Tracks::recall_product(Origin::signed(account_key(TEST_ORGANIZATION)), TEST_PRODUCT_ID.as_bytes().to_owned()),
+			Error::<TestRuntime>::ProductIsUnknown
+		);
+	})
+}
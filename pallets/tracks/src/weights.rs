@@ -0,0 +1,133 @@
+//! Weights of the tracks pallet's extrinsics.
+//!
+//! These weights are estimates, not benchmark output. The storage reads and writes of each
+//! call are counted from the code paths measured by `benchmarking.rs`, in the same worst case,
+//! and the execution times are estimated. Runtimes should generate their own weights:
+//!
+//! ```text
+//! node benchmark --chain dev --pallet tracks --extrinsic '*' --steps 50 --repeat 20
+//! ```
+
+use frame_support::{
+	traits::Get,
+	weights::{constants::RocksDbWeight as DbWeight, Weight},
+};
+
+pub trait WeightInfo {
+	fn register_shipment() -> Weight;
+	fn record_event(r: u32, c: u32) -> Weight;
+	fn confirm_delivery() -> Weight;
+	fn dispute_delivery() -> Weight;
+	fn hand_over_custody() -> Weight;
+	fn register_device() -> Weight;
+	fn revoke_device() -> Weight;
+	fn aggregate_shipments(c: u32) -> Weight;
+	fn set_geofence() -> Weight;
+	fn set_threshold_rule() -> Weight;
+	fn remove_threshold_rule() -> Weight;
+	fn grant_role() -> Weight;
+	fn revoke_role() -> Weight;
+}
+
+impl WeightInfo for () {
+	// Flat in the number of deadlines queued in the shipment's bucket. Products and payment
+	// are weighed by the call.
+	fn register_shipment() -> Weight {
+		(65_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(7))
+			.saturating_add(DbWeight::get().writes(9))
+	}
+
+	// `r` is the number of readings, each with a device signature to verify, and `c` the
+	// number of shipments loaded in the container the event reaches. Each shipment checks
+	// its products' recall, its rules against every reading, and is settled on delivery.
+	fn record_event(r: u32, c: u32) -> Weight {
+		let (r, c) = (r as Weight, c as Weight);
+		(110_000_000 as Weight)
+			.saturating_add((52_000_000 as Weight).saturating_mul(r))
+			.saturating_add((45_000_000 as Weight).saturating_mul(c))
+			.saturating_add((3_000_000 as Weight).saturating_mul(r.saturating_mul(c)))
+			.saturating_add(DbWeight::get().reads(25))
+			.saturating_add(DbWeight::get().reads((3 as Weight).saturating_mul(r)))
+			.saturating_add(DbWeight::get().reads((20 as Weight).saturating_mul(c)))
+			.saturating_add(DbWeight::get().reads((2 as Weight).saturating_mul(r.saturating_mul(c))))
+			.saturating_add(DbWeight::get().writes(12))
+			.saturating_add(DbWeight::get().writes(r))
+			.saturating_add(DbWeight::get().writes((9 as Weight).saturating_mul(c)))
+			.saturating_add(DbWeight::get().writes(r.saturating_mul(c)))
+	}
+
+	// Reads each product's recall, completes the delivery and settles its payment
+	fn confirm_delivery() -> Weight {
+		(60_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(18))
+			.saturating_add(DbWeight::get().writes(8))
+	}
+
+	fn dispute_delivery() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(3))
+			.saturating_add(DbWeight::get().writes(2))
+	}
+
+	fn hand_over_custody() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(2))
+			.saturating_add(DbWeight::get().writes(1))
+	}
+
+	// Owners registering a device through a delegate read the delegation
+	fn register_device() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(3))
+			.saturating_add(DbWeight::get().writes(1))
+	}
+
+	fn revoke_device() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(3))
+			.saturating_add(DbWeight::get().writes(1))
+	}
+
+	// `c` is the number of shipments loaded in the container, each checked and linked to it
+	fn aggregate_shipments(c: u32) -> Weight {
+		let c = c as Weight;
+		(35_000_000 as Weight)
+			.saturating_add((12_000_000 as Weight).saturating_mul(c))
+			.saturating_add(DbWeight::get().reads(4))
+			.saturating_add(DbWeight::get().reads((3 as Weight).saturating_mul(c)))
+			.saturating_add(DbWeight::get().writes(1))
+			.saturating_add(DbWeight::get().writes(c))
+	}
+
+	// Polygons have at most `GEOFENCE_MAX_VERTICES` vertices to check
+	fn set_geofence() -> Weight {
+		(35_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(3))
+			.saturating_add(DbWeight::get().writes(1))
+	}
+
+	fn set_threshold_rule() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(3))
+			.saturating_add(DbWeight::get().writes(1))
+	}
+
+	fn remove_threshold_rule() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(4))
+			.saturating_add(DbWeight::get().writes(1))
+	}
+
+	fn grant_role() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(2))
+			.saturating_add(DbWeight::get().writes(2))
+	}
+
+	fn revoke_role() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads(2))
+			.saturating_add(DbWeight::get().writes(2))
+	}
+}
@@ -1,9 +1,11 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-mod util;
+pub mod util;
 
 use sp_std::collections::btree_set::BTreeSet;
 
+pub use util::Relation;
+
 /// Types that implement the AccountSet trait are able to supply a set of accounts
 /// The trait is generic over the notion of Account used.
 pub trait AccountSet {
@@ -12,3 +14,41 @@ pub trait AccountSet {
 	fn accounts() -> BTreeSet<Self::AccountId>;
 }
 
+/// Types that implement the RelationSet trait are able to tell whether a parent/child
+/// relation between two organizations (or accounts acting for them) is currently in force.
+/// The trait is generic over the notion of organization used.
+pub trait RelationSet {
+	type OrgId;
+
+	fn contains(relation: &Relation<Self::OrgId>) -> bool;
+}
+
+/// Types that implement the ProductRegistry trait are able to tell whether a product is
+/// currently registered, and which account owns it. The trait is generic over the notions
+/// of product identifier and account used.
+pub trait ProductRegistry {
+	type ProductId;
+	type AccountId;
+
+	fn contains(id: &Self::ProductId) -> bool;
+
+	fn owner_of(id: &Self::ProductId) -> Option<Self::AccountId>;
+}
+
+/// Types that implement the Escrow trait are able to set funds of an account aside, until they
+/// are either paid to another account or refunded. The trait is generic over the notions of
+/// account and balance used.
+pub trait Escrow {
+	type AccountId;
+	type Balance;
+
+	/// Sets `amount` of the account's funds aside. Returns false, leaving the funds untouched,
+	/// when the account can't afford it.
+	fn lock(who: &Self::AccountId, amount: Self::Balance) -> bool;
+
+	/// Pays `amount` set aside by `from` to `to`
+	fn release(from: &Self::AccountId, to: &Self::AccountId, amount: Self::Balance);
+
+	/// Returns `amount` set aside by the account to its funds
+	fn refund(who: &Self::AccountId, amount: Self::Balance);
+}
@@ -5,12 +5,21 @@ use parity_scale_codec::{
 // use sp_runtime::RuntimeDebug;
 use sp_std::prelude::*;
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+/// A directed relation between two organizations, e.g. an organization (`parent`)
+/// delegating the right to act on its behalf to another account (`child`).
+#[derive(Encode, Decode, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "std", derive(Debug))]
 pub struct Relation<OrgId> {
     pub parent: OrgId,
     pub child: OrgId,
 }
 
+impl<OrgId> Relation<OrgId> {
+    pub fn new(parent: OrgId, child: OrgId) -> Self {
+        Self { parent, child }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;